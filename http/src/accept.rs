@@ -0,0 +1,177 @@
+use std::cmp::Reverse;
+use crate::media_type::{split_unquoted, MediaType};
+
+/// One element of an `Accept*` header, e.g. `text/html;level=1;q=0.5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityItem {
+    // media range, language range, charset or coding, as sent
+    pub value: String,
+    // parameters before `q`, only used by `Accept`
+    pub params: Vec<(String, String)>,
+    // the q-value in thousandths, 0 means "not acceptable"
+    pub quality: u16,
+}
+
+/// Parses a comma separated list with q-values, sorted by quality, highest first.
+/// Elements with an invalid q-value are skipped.
+pub fn parse_quality_values(header: &str) -> Vec<QualityItem> {
+    let mut items: Vec<QualityItem> = split_unquoted(header, ',').into_iter().filter_map(|element| {
+        let mut parts = split_unquoted(element, ';').into_iter();
+        let value = parts.next()?.trim();
+        if value.is_empty() {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut quality = 1000;
+        for param in parts {
+            let (name, param_value) = param.split_once('=').unwrap_or((param, ""));
+            let name = name.trim().to_ascii_lowercase();
+            if name == "q" {
+                quality = parse_quality(param_value.trim())?;
+                // anything after q are accept-ext parameters
+                break;
+            }
+            params.push((name, param_value.trim().to_string()));
+        }
+        Some(QualityItem { value: value.to_string(), params, quality })
+    }).collect();
+    items.sort_by_key(|it| Reverse(it.quality));
+    items
+}
+
+fn parse_quality(s: &str) -> Option<u16> {
+    let (int, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|it| it.is_ascii_digit()) {
+        return None;
+    }
+    let fraction = format!("{:0<3}", fraction).parse::<u16>().ok()?;
+    match int {
+        "0" => Some(fraction),
+        "1" if fraction == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+/// Picks the offered media type the `Accept` header prefers, `None` when no offer is acceptable.
+/// Without the header every offer is acceptable, ties go to the earlier offer.
+pub fn negotiate_media_type<'a>(header: Option<&str>, offers: &[&'a str]) -> Option<&'a str> {
+    negotiate(header, offers, |range, offer| {
+        let offer = MediaType::parse(offer)?;
+        let mime = range.value.to_ascii_lowercase();
+        let (main, sub) = mime.split_once('/')?;
+        let (offer_main, offer_sub) = offer.mime.split_once('/')?;
+        let params_match = range.params.iter().all(|(name, value)| offer.param(name) == Some(value));
+        match (main, sub) {
+            ("*", "*") => Some(0),
+            (_, "*") if main == offer_main => Some(1),
+            _ if main == offer_main && sub == offer_sub && params_match => Some(2 + range.params.len()),
+            _ => None,
+        }
+    })
+}
+
+/// Picks the offered language tag the `Accept-Language` header prefers. A range matches
+/// the tags it is a prefix of, so `en` matches `en-US`.
+pub fn negotiate_language<'a>(header: Option<&str>, offers: &[&'a str]) -> Option<&'a str> {
+    negotiate(header, offers, |range, offer| {
+        let range = range.value.to_ascii_lowercase();
+        let offer = offer.to_ascii_lowercase();
+        match offer.strip_prefix(&range) {
+            _ if range == "*" => Some(0),
+            Some(rest) if rest.is_empty() || rest.starts_with('-') => Some(range.len()),
+            _ => None,
+        }
+    })
+}
+
+pub fn negotiate_charset<'a>(header: Option<&str>, offers: &[&'a str]) -> Option<&'a str> {
+    negotiate(header, offers, match_token)
+}
+
+/// Picks the offered content coding the `Accept-Encoding` header prefers. `identity` is
+/// acceptable unless it is excluded explicitly or by `*;q=0`.
+pub fn negotiate_encoding<'a>(header: Option<&str>, offers: &[&'a str]) -> Option<&'a str> {
+    let header = header.map(|header| {
+        let mentions_identity = parse_quality_values(header).iter().any(|it| it.value == "*" || it.value.eq_ignore_ascii_case("identity"));
+        if mentions_identity { header.to_string() } else { format!("{}, identity;q=0.001", header) }
+    });
+    negotiate(header.as_deref(), offers, match_token)
+}
+
+fn match_token(range: &QualityItem, offer: &str) -> Option<usize> {
+    match &range.value[..] {
+        "*" => Some(0),
+        value if value.eq_ignore_ascii_case(offer) => Some(1),
+        _ => None,
+    }
+}
+
+/// The quality of an offer comes from the most specific range matching it, the offer with
+/// the highest quality wins.
+fn negotiate<'a, F>(header: Option<&str>, offers: &[&'a str], specificity: F) -> Option<&'a str>
+    where F: Fn(&QualityItem, &str) -> Option<usize> {
+    let ranges = match header {
+        Some(header) => parse_quality_values(header),
+        None => return offers.first().copied(),
+    };
+    let mut best: Option<(&str, u16)> = None;
+    for offer in offers {
+        let quality = ranges.iter()
+            .filter_map(|range| specificity(range, offer).map(|it| (it, range.quality)))
+            // the first of equally specific ranges wins, they are sorted by quality
+            .fold(None, |best: Option<(usize, u16)>, it| match best {
+                Some(best) if best.0 >= it.0 => Some(best),
+                _ => Some(it),
+            })
+            .map_or(0, |it| it.1);
+        if quality > 0 && best.map_or(true, |best| quality > best.1) {
+            best = Some((offer, quality));
+        }
+    }
+    best.map(|it| it.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_quality_values() {
+        let items = parse_quality_values("text/html;level=1, text/*;q=0.3, */*;q=0.01, x;q=2, y;q=0.1234");
+        let values: Vec<(&str, u16)> = items.iter().map(|it| (&it.value[..], it.quality)).collect();
+        assert_eq!(values, vec![("text/html", 1000), ("text/*", 300), ("*/*", 10)]);
+        assert_eq!(items[0].params, vec![(String::from("level"), String::from("1"))]);
+        assert_eq!(parse_quality_values(" , "), vec![]);
+    }
+
+    #[test]
+    fn test_negotiate_media_type() {
+        let offers = ["application/json", "text/html"];
+        assert_eq!(negotiate_media_type(None, &offers), Some("application/json"));
+        assert_eq!(negotiate_media_type(Some("text/html, application/json;q=0.9"), &offers), Some("text/html"));
+        assert_eq!(negotiate_media_type(Some("text/*, */*;q=0.1"), &offers), Some("text/html"));
+        assert_eq!(negotiate_media_type(Some("*/*"), &offers), Some("application/json"));
+        assert_eq!(negotiate_media_type(Some("*/*, application/json;q=0"), &offers), Some("text/html"));
+        assert_eq!(negotiate_media_type(Some("image/png"), &offers), None);
+        assert_eq!(negotiate_media_type(Some("text/html;level=1"), &["text/html", "text/html;level=1"]), Some("text/html;level=1"));
+    }
+
+    #[test]
+    fn test_negotiate_language() {
+        let offers = ["en-US", "de", "fr-CA"];
+        assert_eq!(negotiate_language(Some("de-DE, fr;q=0.8, en;q=0.5"), &offers), Some("fr-CA"));
+        assert_eq!(negotiate_language(Some("EN"), &offers), Some("en-US"));
+        assert_eq!(negotiate_language(Some("*;q=0.5, de;q=0"), &["de", "en"]), Some("en"));
+        assert_eq!(negotiate_language(Some("e"), &offers), None);
+    }
+
+    #[test]
+    fn test_negotiate_charset_and_encoding() {
+        assert_eq!(negotiate_charset(Some("iso-8859-1, UTF-8;q=0.9"), &["utf-8", "iso-8859-1"]), Some("iso-8859-1"));
+        assert_eq!(negotiate_encoding(Some("gzip;q=0.5, br"), &["gzip", "br", "identity"]), Some("br"));
+        assert_eq!(negotiate_encoding(Some("br"), &["gzip", "identity"]), Some("identity"));
+        assert_eq!(negotiate_encoding(Some("br, *;q=0"), &["gzip", "identity"]), None);
+        assert_eq!(negotiate_encoding(Some("identity;q=0"), &["identity"]), None);
+        assert_eq!(negotiate_encoding(None, &["gzip", "identity"]), Some("gzip"));
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::io::{self, Read};
+
+type Chunks = Box<dyn Iterator<Item=io::Result<Vec<u8>>> + Send>;
+
+/// A response body. Bodies without a known length are sent with
+/// `Transfer-Encoding: chunked`, or until the connection closes for HTTP/1.0 clients.
+pub enum Body {
+    Bytes(Vec<u8>),
+    // read to the end, with the number of bytes when it is known up front
+    Reader(Box<dyn Read + Send>, Option<u64>),
+    // each item is sent as one chunk as soon as it is produced
+    Chunks(Chunks),
+}
+
+impl Body {
+    pub fn reader<R: Read + Send + 'static>(reader: R) -> Self {
+        Body::Reader(Box::new(reader), None)
+    }
+
+    /// A reader that yields exactly `length` bytes, sent with a `Content-Length`.
+    pub fn sized_reader<R: Read + Send + 'static>(reader: R, length: u64) -> Self {
+        Body::Reader(Box::new(reader), Some(length))
+    }
+
+    pub fn chunks<I>(chunks: I) -> Self
+        where I: Iterator<Item=io::Result<Vec<u8>>> + Send + 'static {
+        Body::Chunks(Box::new(chunks))
+    }
+
+    /// Calls the producer for each chunk until it returns `None`.
+    pub fn from_fn<F>(producer: F) -> Self
+        where F: FnMut() -> Option<io::Result<Vec<u8>>> + Send + 'static {
+        Body::chunks(std::iter::from_fn(producer))
+    }
+
+    /// The length of the body, `None` when it is only known after it was sent.
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Reader(_, length) => *length,
+            Body::Chunks(_) => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// The body as one stream of bytes, e.g. to feed it through an encoder.
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        match self {
+            Body::Bytes(bytes) => Box::new(io::Cursor::new(bytes)),
+            Body::Reader(reader, Some(length)) => Box::new(reader.take(length)),
+            Body::Reader(reader, None) => reader,
+            Body::Chunks(chunks) => Box::new(ChunksReader { chunks, chunk: io::Cursor::new(Vec::new()) }),
+        }
+    }
+}
+
+struct ChunksReader {
+    chunks: Chunks,
+    chunk: io::Cursor<Vec<u8>>,
+}
+
+impl Read for ChunksReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.chunk.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.chunk = io::Cursor::new(chunk?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::Bytes(bytes)
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Body::Reader(_, length) => write!(f, "Reader({:?})", length),
+            Body::Chunks(_) => write!(f, "Chunks"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_into_reader() {
+        let chunks = vec![Ok(b"ab".to_vec()), Ok(Vec::new()), Ok(b"cde".to_vec())];
+        let mut output = String::new();
+        Body::chunks(chunks.into_iter()).into_reader().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "abcde");
+
+        let mut output = String::new();
+        Body::sized_reader("hello world".as_bytes(), 5).into_reader().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "hello");
+    }
+}
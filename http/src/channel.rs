@@ -1,26 +1,102 @@
 use std::io;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::net::TcpStream;
-use crate::request::HttpRequest;
+use crate::body::Body;
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+use crate::multipart::{Multipart, MultipartLimits};
+use crate::request::{HttpMethod, HttpRequest, HttpVersion, ParseError};
 use crate::response::HttpResponse;
+use crate::sse::EventStream;
 
 pub struct HttpChannel<'a> {
     pub request: &'a HttpRequest,
     pub response: &'a mut HttpResponse,
     pub stream: &'a mut TcpStream,
     pub is_sent: bool,
+    // the unread body for routes with `Route::stream_body`
+    body: Option<&'a mut dyn Read>,
+    // set by the router for routes with compression
+    #[cfg(feature = "compression")]
+    pub compression: Option<Compression>,
 }
 
 impl<'a> HttpChannel<'a> {
     pub fn new(request: &'a HttpRequest, response: &'a mut HttpResponse, stream: &'a mut TcpStream) -> Self {
-        Self { request, response, stream, is_sent: false }
+        Self {
+            request, response, stream, is_sent: false, body: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
+
+    /// Sets the body that is read from the connection while the handler runs.
+    pub fn streamed_body(&mut self, body: &'a mut dyn Read) -> &mut Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// The request body, read from the connection for routes with `Route::stream_body`
+    /// and from the buffered body otherwise.
+    pub fn body_reader(&mut self) -> Box<dyn Read + '_> {
+        match &mut self.body {
+            Some(body) => Box::new(&mut **body),
+            None => Box::new(self.request.body().unwrap_or(&[])),
+        }
+    }
+
+    /// A parser over the parts of a `multipart/form-data` body. With `Route::stream_body`
+    /// the parts are read as they arrive, large files go to temp files without being buffered.
+    pub fn multipart(&mut self, limits: MultipartLimits) -> Result<Multipart<Box<dyn Read + '_>>, ParseError> {
+        let boundary = self.request.multipart_boundary()?;
+        Ok(Multipart::new(self.body_reader(), &boundary, limits))
     }
 
     pub fn send(&mut self, b: &[u8]) -> io::Result<()> {
-        self.stream.write(self.response.to_string().as_bytes())?;
-        self.stream.write(b)?;
+        self.send_body(Body::Bytes(b.to_vec()))
+    }
+
+    /// Sends the response with a body that is written while it is produced, e.g. a large export.
+    pub fn send_body(&mut self, body: Body) -> io::Result<()> {
+        self.response.body_stream(body);
+        #[cfg(feature = "compression")]
+        self.compress()?;
+        let result = self.response.write_to(self.stream).and_then(|_| self.stream.flush());
+        // a body that fails after the head ends the connection, `write_to` closes it
+        self.is_sent = self.response.head_sent();
+        result
+    }
+
+    /// Sends the response head for a `text/event-stream` and returns the stream to write
+    /// events to. The response stays open until the stream is closed or dropped. For a HEAD
+    /// request only the head is sent, the events are dropped.
+    pub fn event_stream(&mut self) -> io::Result<EventStream<&mut TcpStream>> {
+        let chunked = self.request.version == HttpVersion::V1_1;
+        self.response.success()
+            .header(String::from("Content-Type"), String::from("text/event-stream"))
+            .header(String::from("Cache-Control"), String::from("no-cache"));
+        self.response.headers.remove("Content-Length");
+        if chunked {
+            self.response.header(String::from("Transfer-Encoding"), String::from("chunked"));
+        } else {
+            self.response.header(String::from("Connection"), String::from("close"));
+        }
+        self.response.write_head(self.stream)?;
         self.stream.flush()?;
         self.is_sent = true;
-        Ok(())
+        let last_event_id = self.request.header("Last-Event-ID").map(|it| it.to_string());
+        if self.request.method == HttpMethod::HEAD {
+            return Ok(EventStream::discard(last_event_id));
+        }
+        Ok(EventStream::new(&mut *self.stream, chunked, last_event_id))
     }
-}
\ No newline at end of file
+
+    /// Compresses the response body if the route has compression enabled.
+    #[cfg(feature = "compression")]
+    pub fn compress(&mut self) -> io::Result<()> {
+        match &self.compression {
+            Some(compression) => compression.apply(self.request, self.response),
+            None => Ok(()),
+        }
+    }
+}
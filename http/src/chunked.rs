@@ -0,0 +1,250 @@
+use std::io::{self, BufRead, Read, Write};
+use crate::request::{parse_header, ParseError};
+use crate::utils::decode_latin1;
+
+pub type Trailers = Vec<(String, String)>;
+
+// longest chunk size or trailer line accepted
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+const MAX_TRAILERS: usize = 100;
+
+/// Decodes a `Transfer-Encoding: chunked` body of at most `max_size` bytes from the reader.
+/// Chunk extensions are skipped, returns the payload with the trailer headers.
+pub fn decode_chunked<R: BufRead>(reader: &mut R, max_size: usize) -> Result<(Vec<u8>, Trailers), ParseError> {
+    let mut chunked = ChunkedReader::new(reader, max_size);
+    let mut body = Vec::new();
+    chunked.read_to_end(&mut body)?;
+    Ok((body, chunked.trailers.unwrap_or_default()))
+}
+
+/// Decodes a chunked body while it is read, for bodies that are processed as they arrive.
+/// Errors are `ParseError`s wrapped in `io::Error`, `ParseError::from` unwraps them.
+pub struct ChunkedReader<R: BufRead> {
+    reader: R,
+    max_size: usize,
+    // payload bytes announced by the chunks so far
+    size: usize,
+    // bytes left in the current chunk
+    remaining: usize,
+    // set once the last chunk and the trailers were read
+    trailers: Option<Trailers>,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    pub fn new(reader: R, max_size: usize) -> Self {
+        ChunkedReader { reader, max_size, size: 0, remaining: 0, trailers: None }
+    }
+
+    /// Whether the whole body including the trailers was read.
+    pub fn is_finished(&self) -> bool {
+        self.trailers.is_some()
+    }
+
+    /// The trailers, available once the body is read to the end.
+    pub fn trailers(&self) -> Option<&Trailers> {
+        self.trailers.as_ref()
+    }
+
+    // reads the next chunk size line, or the trailers after the last chunk
+    fn next_chunk(&mut self) -> Result<(), ParseError> {
+        let line = read_line(&mut self.reader)?;
+        // hex digits only, a sign or whitespace a proxy might read differently is rejected
+        let size = line.split(';').next().unwrap_or("");
+        let size = Some(size).filter(|it| !it.is_empty() && it.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|it| usize::from_str_radix(it, 16).ok())
+            .ok_or_else(|| ParseError::InvalidBody(format!("invalid chunk size: {}", line)))?;
+        if size == 0 {
+            self.trailers = Some(read_trailers(&mut self.reader)?);
+            return Ok(());
+        }
+        if size > self.max_size - self.size {
+            return Err(ParseError::PayloadTooLarge);
+        }
+        self.size += size;
+        self.remaining = size;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.is_finished() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.next_chunk()?;
+            if self.is_finished() {
+                return Ok(0);
+            }
+        }
+        let max = self.remaining.min(buf.len());
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed inside a chunk"));
+        }
+        self.remaining -= n;
+        if self.remaining == 0 && !read_line(&mut self.reader)?.is_empty() {
+            return Err(ParseError::InvalidBody(String::from("missing CRLF after chunk data")).into());
+        }
+        Ok(n)
+    }
+}
+
+fn read_trailers<R: BufRead>(reader: &mut R) -> Result<Trailers, ParseError> {
+    let mut trailers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(trailers);
+        }
+        if trailers.len() == MAX_TRAILERS {
+            return Err(ParseError::HeaderTooLarge);
+        }
+        trailers.push(parse_header(&line)?);
+    }
+}
+
+/// Returns true when `chunked` is the final coding of a `Transfer-Encoding` value.
+pub fn is_chunked(transfer_encoding: &str) -> bool {
+    transfer_encoding.rsplit(',').next().is_some_and(|it| it.trim().eq_ignore_ascii_case("chunked"))
+}
+
+/// Encodes what is written to it as `Transfer-Encoding: chunked`, each write becomes a chunk.
+/// `finish` writes the last chunk and the trailers.
+pub struct ChunkedWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ChunkedWriter { writer }
+    }
+
+    pub fn finish(mut self, trailers: &[(&str, &str)]) -> io::Result<W> {
+        let mut end = String::from("0\r\n");
+        for (name, value) in trailers {
+            end.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        end.push_str("\r\n");
+        self.writer.write_all(end.as_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // an empty chunk would end the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.writer, "{:x}\r\n", buf.len())?;
+        self.writer.write_all(buf)?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, ParseError> {
+    let mut line = Vec::new();
+    let n = reader.by_ref().take(MAX_LINE_LENGTH).read_until(b'\n', &mut line)?;
+    if n as u64 == MAX_LINE_LENGTH && !line.ends_with(b"\n") {
+        return Err(ParseError::InvalidBody(String::from("chunk line too long")));
+    }
+    if !line.ends_with(b"\n") {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed inside a chunked body").into());
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(decode_latin1(&line))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_chunked() {
+        let mut s = "5\r\nhello\r\n7;name=value\r\n, world\r\n0\r\n\r\nrest".as_bytes();
+        let (body, trailers) = decode_chunked(&mut s, usize::MAX).unwrap();
+        assert_eq!(body, b"hello, world");
+        assert!(trailers.is_empty());
+        assert_eq!(s, b"rest");
+    }
+
+    #[test]
+    fn test_decode_trailers() {
+        let mut s = "A\r\n0123456789\r\n0\r\nExpires: never\r\nX-Checksum: abc\r\n\r\n".as_bytes();
+        let (body, trailers) = decode_chunked(&mut s, usize::MAX).unwrap();
+        assert_eq!(body, b"0123456789");
+        assert_eq!(trailers, vec![(String::from("Expires"), String::from("never")),
+                                  (String::from("X-Checksum"), String::from("abc"))]);
+    }
+
+    #[test]
+    fn test_decode_error() {
+        assert!(matches!(decode_chunked(&mut "z\r\nhello\r\n0\r\n\r\n".as_bytes(), usize::MAX), Err(ParseError::InvalidBody(_))));
+        assert!(matches!(decode_chunked(&mut "5\r\nhelloX\r\n0\r\n\r\n".as_bytes(), usize::MAX), Err(ParseError::InvalidBody(_))));
+        assert!(matches!(decode_chunked(&mut "5\r\nhel".as_bytes(), usize::MAX), Err(ParseError::Io(_))));
+        assert!(matches!(decode_chunked(&mut "0\r\nbad trailer\r\n\r\n".as_bytes(), usize::MAX), Err(ParseError::BadHeader(_))));
+        for size in ["+5", " 5", "5 ", "-5", "0x5", ""] {
+            let body = format!("{}\r\nhello\r\n0\r\n\r\n", size);
+            assert!(matches!(decode_chunked(&mut body.as_bytes(), usize::MAX), Err(ParseError::InvalidBody(_))), "{:?}", size);
+        }
+        assert_eq!(decode_chunked(&mut "5;name=value\r\nhello\r\n0\r\n\r\n".as_bytes(), usize::MAX).unwrap().0, b"hello");
+    }
+
+    #[test]
+    fn test_max_size() {
+        assert!(decode_chunked(&mut "5\r\nhello\r\n0\r\n\r\n".as_bytes(), 5).is_ok());
+        assert!(matches!(decode_chunked(&mut "5\r\nhello\r\n1\r\n!\r\n0\r\n\r\n".as_bytes(), 5), Err(ParseError::PayloadTooLarge)));
+        assert!(matches!(decode_chunked(&mut "ffffffffffffffff\r\n".as_bytes(), 5), Err(ParseError::PayloadTooLarge)));
+        assert!(matches!(decode_chunked(&mut "fffffff\r\nab\r\n".as_bytes(), usize::MAX), Err(ParseError::Io(_))));
+    }
+
+    #[test]
+    fn test_chunked_reader() {
+        let mut s = "4\r\nWiki\r\n5\r\npedia\r\n0\r\nX-Sum: 1\r\n\r\nrest".as_bytes();
+        let mut chunked = ChunkedReader::new(&mut s, 100);
+        let mut buf = [0; 3];
+        assert_eq!(chunked.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"Wik");
+        assert!(!chunked.is_finished());
+        let mut rest = String::new();
+        chunked.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "ipedia");
+        assert!(chunked.is_finished());
+        assert_eq!(chunked.trailers(), Some(&vec![(String::from("X-Sum"), String::from("1"))]));
+        assert_eq!(s, b"rest");
+
+        let mut chunked = ChunkedReader::new("5\r\nhello\r\n1\r\n!\r\n0\r\n\r\n".as_bytes(), 5);
+        let e = chunked.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(ParseError::from(e), ParseError::PayloadTooLarge));
+    }
+
+    #[test]
+    fn test_is_chunked() {
+        assert!(is_chunked("chunked"));
+        assert!(is_chunked("gzip, Chunked"));
+        assert!(!is_chunked("chunked, gzip"));
+    }
+
+    #[test]
+    fn test_chunked_writer() {
+        let mut writer = ChunkedWriter::new(Vec::new());
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(&[b'x'; 20]).unwrap();
+        let output = writer.finish(&[("X-Sum", "1")]).unwrap();
+        let (body, trailers) = decode_chunked(&mut &output[..], usize::MAX).unwrap();
+        assert_eq!(body, [&b"hello"[..], &[b'x'; 20]].concat());
+        assert_eq!(trailers, vec![(String::from("X-Sum"), String::from("1"))]);
+        assert!(output.starts_with(b"5\r\nhello\r\n14\r\n"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::io::{self, Read};
+use flate2::read::{DeflateEncoder, GzEncoder};
+use crate::body::Body;
+use crate::request::HttpRequest;
+use crate::response::HttpResponse;
+use crate::status::StatusCode;
+
+// content types whose payload is compressed already
+const COMPRESSED_TYPES: [&str; 12] = [
+    "image/", "audio/", "video/", "font/woff", "font/woff2", "application/zip", "application/gzip",
+    "application/x-gzip", "application/x-bzip2", "application/x-7z-compressed", "application/zstd",
+    // compressing would buffer the events
+    "text/event-stream",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    /// The name used in `Accept-Encoding` and `Content-Encoding`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn encode(&self, reader: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+        match self {
+            Encoding::Brotli => Box::new(brotli::CompressorReader::new(reader, 4096, 5, 22)),
+            Encoding::Gzip => Box::new(GzEncoder::new(reader, flate2::Compression::default())),
+            Encoding::Deflate => Box::new(DeflateEncoder::new(reader, flate2::Compression::default())),
+        }
+    }
+}
+
+/// Compresses response bodies with the coding the client prefers. Enabled for a whole
+/// router with `HttpRouter::compression` or for one route with `Route::compression`.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    // codings offered, the first is used when the client accepts several equally
+    pub encodings: Vec<Encoding>,
+    // bodies of a known length below this are sent as they are
+    pub min_size: u64,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression { encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate], min_size: 1024 }
+    }
+}
+
+impl Compression {
+    /// Compresses the body of the response when it is worth it and the request accepts one
+    /// of the codings. Sets `Content-Encoding` and `Vary`, and fixes up `Content-Length`.
+    /// Only a body held in memory is compressed up front, a streamed one is sent chunked.
+    pub fn apply(&self, http_request: &HttpRequest, http_response: &mut HttpResponse) -> io::Result<()> {
+        if !self.is_compressible(http_response) {
+            return Ok(());
+        }
+        add_vary(http_response);
+        let encoding = match self.negotiate(http_request) {
+            Some(encoding) => encoding,
+            None => return Ok(()),
+        };
+        let body = match http_response.take_body() {
+            Some(body) => body,
+            None => return Ok(()),
+        };
+        // a body in memory is compressed in memory, a streamed one, e.g. a file, while it is sent
+        let in_memory = matches!(body, Body::Bytes(_));
+        let mut reader = encoding.encode(body.into_reader());
+        http_response.header(String::from("Content-Encoding"), String::from(encoding.name()));
+        if in_memory {
+            let mut compressed = Vec::new();
+            reader.read_to_end(&mut compressed)?;
+            http_response.body(compressed);
+        } else {
+            http_response.body_stream(Body::Reader(reader, None));
+        }
+        Ok(())
+    }
+
+    fn negotiate(&self, http_request: &HttpRequest) -> Option<Encoding> {
+        // without the header any coding is acceptable, but identity is the safe choice
+        http_request.header("Accept-Encoding")?;
+        let mut offers: Vec<&str> = self.encodings.iter().map(|it| it.name()).collect();
+        offers.push("identity");
+        let name = http_request.negotiate_encoding(&offers)?;
+        self.encodings.iter().find(|it| it.name() == name).copied()
+    }
+
+    fn is_compressible(&self, http_response: &HttpResponse) -> bool {
+        let status = http_response.status;
+        if !http_response.has_body() || status.is_informational() || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED {
+            return false;
+        }
+        let headers = &http_response.headers;
+        if headers.contains_key("Content-Encoding") || headers.get_all("Cache-Control").any(|it| it.contains("no-transform")) {
+            return false;
+        }
+        let content_type = headers.get("Content-Type").unwrap_or("").trim().to_ascii_lowercase();
+        if COMPRESSED_TYPES.iter().any(|it| content_type.starts_with(it)) && !content_type.starts_with("image/svg+xml") {
+            return false;
+        }
+        http_response.body_len().map_or(true, |it| it >= self.min_size)
+    }
+}
+
+fn add_vary(http_response: &mut HttpResponse) {
+    let varies = http_response.headers.get_all("Vary")
+        .flat_map(|it| it.split(','))
+        .any(|it| it.trim() == "*" || it.trim().eq_ignore_ascii_case("Accept-Encoding"));
+    if !varies {
+        http_response.append_header(String::from("Vary"), String::from("Accept-Encoding"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use flate2::read::GzDecoder;
+    use super::*;
+
+    fn request(accept_encoding: &str) -> HttpRequest {
+        HttpRequest::new(format!("GET / HTTP/1.1\nHost: a\nAccept-Encoding: {}\n\n", accept_encoding)).unwrap()
+    }
+
+    #[test]
+    fn test_gzip_body() {
+        let body = "{\"name\": \"value\"}".repeat(100);
+        let mut http_response = HttpResponse::new();
+        http_response.header(String::from("Content-Type"), String::from("application/json")).body_str(body.clone());
+        Compression::default().apply(&request("gzip, deflate"), &mut http_response).unwrap();
+        assert_eq!(http_response.headers.get("Content-Encoding"), Some("gzip"));
+        assert_eq!(http_response.headers.get("Vary"), Some("Accept-Encoding"));
+        let compressed = http_response.body_ref().unwrap();
+        assert_eq!(http_response.headers.get("Content-Length"), Some(&compressed.len().to_string()[..]));
+        let mut decoded = String::new();
+        GzDecoder::new(compressed).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[test]
+    fn test_brotli_stream() {
+        let mut http_response = HttpResponse::new();
+        http_response.body_stream(Body::reader(io::Cursor::new("a,b\n".repeat(1000).into_bytes())));
+        Compression::default().apply(&request("gzip;q=0.5, br"), &mut http_response).unwrap();
+        assert_eq!(http_response.headers.get("Content-Encoding"), Some("br"));
+        assert_eq!(http_response.headers.get("Content-Length"), None);
+        let mut output = Vec::new();
+        http_response.write_to(&mut output).unwrap();
+        assert!(String::from_utf8_lossy(&output).contains("Transfer-Encoding: chunked"));
+
+        // a streamed body of a known length isn't read into memory either
+        let mut http_response = HttpResponse::new();
+        http_response.body_stream(Body::sized_reader(io::Cursor::new("a,b\n".repeat(1000).into_bytes()), 4000));
+        Compression::default().apply(&request("gzip"), &mut http_response).unwrap();
+        assert_eq!(http_response.headers.get("Content-Encoding"), Some("gzip"));
+        assert_eq!(http_response.headers.get("Content-Length"), None);
+        assert_eq!(http_response.body_len(), None);
+        assert_eq!(http_response.body_ref(), None);
+    }
+
+    #[test]
+    fn test_skipped() {
+        let compression = Compression::default();
+        let large = "x".repeat(2000);
+
+        let mut small = HttpResponse::new();
+        small.body_str(String::from("hello"));
+        compression.apply(&request("gzip"), &mut small).unwrap();
+        assert_eq!(small.headers.get("Content-Encoding"), None);
+        assert_eq!(small.headers.get("Vary"), None);
+
+        let mut image = HttpResponse::new();
+        image.header(String::from("Content-Type"), String::from("image/png")).body_str(large.clone());
+        compression.apply(&request("gzip"), &mut image).unwrap();
+        assert_eq!(image.headers.get("Content-Encoding"), None);
+
+        let mut identity = HttpResponse::new();
+        identity.body_str(large.clone());
+        compression.apply(&request("identity"), &mut identity).unwrap();
+        assert_eq!(identity.headers.get("Content-Encoding"), None);
+        assert_eq!(identity.headers.get("Vary"), Some("Accept-Encoding"));
+
+        let mut no_header = HttpResponse::new();
+        no_header.body_str(large);
+        compression.apply(&HttpRequest::new("GET / HTTP/1.1\nHost: a\n\n").unwrap(), &mut no_header).unwrap();
+        assert_eq!(no_header.body_str_ref().map(|it| it.len()), Some(2000));
+    }
+}
@@ -0,0 +1,42 @@
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The connection a request arrived on, captured once when the connection is accepted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectionInfo {
+    // unique per process, 0 for requests that were not read from a connection
+    pub id: u64,
+    pub peer_addr: Option<SocketAddr>,
+    pub local_addr: Option<SocketAddr>,
+    // 1 for the first request on the connection, counts up for kept-alive connections
+    pub sequence: u64,
+}
+
+impl ConnectionInfo {
+    /// Describes a new connection and gives it the next connection id.
+    pub fn new(peer_addr: Option<SocketAddr>, local_addr: Option<SocketAddr>) -> Self {
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        ConnectionInfo { id, peer_addr, local_addr, sequence: 0 }
+    }
+
+    pub fn from_stream(stream: &TcpStream) -> Self {
+        Self::new(stream.peer_addr().ok(), stream.local_addr().ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unique_ids() {
+        let peer_addr: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+        let first = ConnectionInfo::new(Some(peer_addr), None);
+        let second = ConnectionInfo::new(Some(peer_addr), None);
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.peer_addr, Some(peer_addr));
+        assert_eq!(ConnectionInfo::default().id, 0);
+    }
+}
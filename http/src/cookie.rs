@@ -0,0 +1,207 @@
+use std::error::Error;
+use std::fmt;
+use std::time::SystemTime;
+use crate::date::fmt_http_date;
+use crate::header::is_tchar;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    // browsers only accept it together with Secure
+    None,
+}
+
+/// A cookie name, value or attribute the `Set-Cookie` grammar of RFC 6265 doesn't allow,
+/// e.g. a value with `;` that would add attributes of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidCookie(pub String);
+
+impl fmt::Display for InvalidCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cookie: {}", self.0)
+    }
+}
+
+impl Error for InvalidCookie {}
+
+/// A cookie sent to the client with `Set-Cookie`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    expires: Option<SystemTime>,
+    max_age: Option<i64>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// The name must be a token and the value cookie-octets, optionally in double quotes.
+    pub fn new(name: String, value: String) -> Result<Self, InvalidCookie> {
+        if name.is_empty() || !name.bytes().all(is_tchar) {
+            return Err(InvalidCookie(format!("name {:?}", name)));
+        }
+        let unquoted = value.strip_prefix('"').and_then(|it| it.strip_suffix('"')).unwrap_or(&value);
+        if !unquoted.bytes().all(is_cookie_octet) {
+            return Err(InvalidCookie(format!("value {:?}", value)));
+        }
+        Ok(Cookie {
+            name, value, path: None, domain: None, expires: None, max_age: None,
+            secure: false, http_only: false, same_site: None,
+        })
+    }
+
+    /// A cookie that makes the client delete the cookie with that name.
+    pub fn removal(name: String) -> Result<Self, InvalidCookie> {
+        let mut cookie = Cookie::new(name, String::new())?;
+        cookie.max_age(0).expires(SystemTime::UNIX_EPOCH);
+        Ok(cookie)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The path may contain anything but control characters and `;`.
+    pub fn path(&mut self, path: String) -> Result<&mut Self, InvalidCookie> {
+        if path.chars().any(|it| it.is_control() || it == ';') {
+            return Err(InvalidCookie(format!("path {:?}", path)));
+        }
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    /// A host name, letters, digits, `-` and `.` only.
+    pub fn domain(&mut self, domain: String) -> Result<&mut Self, InvalidCookie> {
+        if domain.is_empty() || !domain.bytes().all(|it| it.is_ascii_alphanumeric() || it == b'-' || it == b'.') {
+            return Err(InvalidCookie(format!("domain {:?}", domain)));
+        }
+        self.domain = Some(domain);
+        Ok(self)
+    }
+
+    pub fn expires(&mut self, expires: SystemTime) -> &mut Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Lifetime in seconds, zero or less deletes the cookie.
+    pub fn max_age(&mut self, seconds: i64) -> &mut Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn secure(&mut self, secure: bool) -> &mut Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(&mut self, http_only: bool) -> &mut Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(&mut self, same_site: SameSite) -> &mut Self {
+        self.same_site = Some(same_site);
+        self
+    }
+}
+
+/// Formats the cookie as a `Set-Cookie` header value.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", fmt_http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => write!(f, "; SameSite=Strict"),
+            Some(SameSite::Lax) => write!(f, "; SameSite=Lax"),
+            Some(SameSite::None) => write!(f, "; SameSite=None"),
+            None => Ok(()),
+        }
+    }
+}
+
+// US-ASCII without controls, whitespace, DQUOTE, comma, semicolon and backslash
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+/// Parses the pairs of a `Cookie` request header, e.g. `a=1; b="2"`.
+pub fn parse_cookie_header(value: &str) -> Vec<(String, String)> {
+    value.split(';').filter_map(|it| {
+        let (name, value) = it.split_once('=')?;
+        let value = value.trim();
+        let value = value.strip_prefix('"').and_then(|it| it.strip_suffix('"')).unwrap_or(value);
+        Some((name.trim().to_string(), value.to_string()))
+    }).filter(|it| !it.0.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::*;
+
+    #[test]
+    fn test_set_cookie() {
+        let mut cookie = Cookie::new(String::from("session"), String::from("abc")).unwrap();
+        assert_eq!(cookie.to_string(), "session=abc");
+        cookie.path(String::from("/")).unwrap()
+            .domain(String::from("example.com")).unwrap()
+            .expires(UNIX_EPOCH + Duration::from_secs(784111777))
+            .max_age(3600)
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(cookie.to_string(), "session=abc; Path=/; Domain=example.com; \
+            Expires=Sun, 06 Nov 1994 08:49:37 GMT; Max-Age=3600; Secure; HttpOnly; SameSite=Lax");
+        assert_eq!(Cookie::removal(String::from("a")).unwrap().to_string(), "a=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0");
+    }
+
+    #[test]
+    fn test_invalid_cookie() {
+        assert!(Cookie::new(String::from("a"), String::from("\"x y\"")).is_err());
+        assert!(Cookie::new(String::from("a"), String::from("1; Domain=evil.com")).is_err());
+        assert!(Cookie::new(String::from("a"), String::from("1\r\nX-Injected: 1")).is_err());
+        assert!(Cookie::new(String::from("a b"), String::from("1")).is_err());
+        assert!(Cookie::new(String::from(""), String::from("1")).is_err());
+        assert_eq!(Cookie::new(String::from("a"), String::from("\"quoted\"")).unwrap().value(), "\"quoted\"");
+
+        let mut cookie = Cookie::new(String::from("a"), String::from("1")).unwrap();
+        assert!(cookie.path(String::from("/; Secure")).is_err());
+        assert!(cookie.domain(String::from("evil.com; Path=/")).is_err());
+        assert_eq!(cookie.to_string(), "a=1");
+    }
+
+    #[test]
+    fn test_parse_cookie_header() {
+        assert_eq!(parse_cookie_header("a=1; b=\"x y\";c=; =bad; novalue"),
+                   vec![(String::from("a"), String::from("1")),
+                        (String::from("b"), String::from("x y")),
+                        (String::from("c"), String::new())]);
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats a time as an RFC 1123 HTTP-date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn fmt_http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |it| it.as_secs());
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[((days + 4) % 7) as usize], day, MONTHS[month as usize - 1], year,
+            secs % 86400 / 3600, secs % 3600 / 60, secs % 60)
+}
+
+// the formatted current time, renewed once per second
+static CURRENT_DATE: Mutex<(u64, String)> = Mutex::new((u64::MAX, String::new()));
+
+/// The current time as an HTTP-date for the `Date` header, formatted at most once per second.
+pub fn http_date_now() -> String {
+    let now = SystemTime::now();
+    let secs = now.duration_since(UNIX_EPOCH).map_or(0, |it| it.as_secs());
+    let mut current = CURRENT_DATE.lock().unwrap_or_else(|it| it.into_inner());
+    if current.0 != secs {
+        *current = (secs, fmt_http_date(now));
+    }
+    current.1.clone()
+}
+
+/// Parses an HTTP-date in the preferred RFC 1123 format, or in the obsolete RFC 850
+/// and asctime formats recipients must accept, e.g. `Sunday, 06-Nov-94 08:49:37 GMT`.
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let s = s.trim();
+    let (weekday, rest) = s.split_once([',', ' '])?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let (year, month, day, time) = match fields[..] {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [day, month, year, time, "GMT"] if year.len() == 4 && WEEKDAYS.contains(&weekday) => {
+            (year.parse().ok()?, month, day, time)
+        }
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [date, time, "GMT"] => {
+            let mut parts = date.split('-');
+            let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+            let year: i64 = year.parse().ok().filter(|_| year.len() == 2)?;
+            (full_year(year, current_year()), month, day, time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [month, day, time, year] if WEEKDAYS.contains(&weekday) => (year.parse().ok()?, month, day, time),
+        _ => return None,
+    };
+    let month = MONTHS.iter().position(|it| *it == month)? as u32 + 1;
+    let day: u32 = day.parse().ok().filter(|it| (1..=days_in_month(year, month)).contains(it) && day.len() <= 2)?;
+    let time: Vec<u64> = time.split(':').map(|it| it.parse().ok().filter(|_| it.len() == 2)).collect::<Option<_>>()?;
+    match time[..] {
+        [hour, minute, second] if hour < 24 && minute < 60 && second < 61 => {
+            let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+            Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + hour * 3600 + minute * 60 + second))
+        }
+        _ => None,
+    }
+}
+
+// a two digit year is the one with those digits nearest to the current year, one that would be
+// more than 50 years ahead is the most recent past one instead (RFC 9110, section 5.6.7)
+fn full_year(year: i64, current_year: i64) -> i64 {
+    let year = current_year - current_year % 100 + year;
+    if year > current_year + 50 {
+        year - 100
+    } else if year <= current_year - 50 {
+        year + 100
+    } else {
+        year
+    }
+}
+
+fn current_year() -> i64 {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |it| it.as_secs());
+    civil_from_days((secs / 86400) as i64).0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Converts a date to days since 1970-01-01, the inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * mp + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date in the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt_http_date() {
+        assert_eq!(fmt_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(fmt_http_date(UNIX_EPOCH + Duration::from_secs(784111777)), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(fmt_http_date(UNIX_EPOCH + Duration::from_secs(951782400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        let time = Some(UNIX_EPOCH + Duration::from_secs(784111777));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), time);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), time);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), time);
+        assert_eq!(parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(951782400)));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1969 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+
+    #[test]
+    fn test_invalid_day() {
+        assert_eq!(parse_http_date("Sat, 31 Feb 2024 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(1709164800)));
+        assert_eq!(parse_http_date("Thu, 29 Feb 1900 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thu, 31 Apr 2025 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thursday, 31-Apr-25 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sun Feb 30 08:49:37 1994"), None);
+    }
+
+    #[test]
+    fn test_two_digit_year() {
+        assert_eq!(full_year(94, 2026), 1994);
+        assert_eq!(full_year(26, 2026), 2026);
+        assert_eq!(full_year(76, 2026), 2076);
+        assert_eq!(full_year(77, 2026), 1977);
+        assert_eq!(full_year(5, 2099), 2105);
+        assert_eq!(full_year(50, 2099), 2050);
+    }
+
+    #[test]
+    fn test_http_date_now() {
+        let now = parse_http_date(&http_date_now()).unwrap();
+        assert!(SystemTime::now().duration_since(now).unwrap() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,42 @@
+use std::error::Error;
+use std::fmt;
+use crate::status::StatusCode;
+
+/// An error that is answered with its own status instead of 500 Internal Server Error.
+/// Handlers return it like any other error, e.g. with `?`.
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: StatusCode,
+    // sent as the response body
+    pub detail: String,
+}
+
+impl HttpError {
+    pub fn new(status: StatusCode, detail: String) -> Self {
+        HttpError { status, detail }
+    }
+
+    pub fn bad_request(detail: String) -> Self {
+        HttpError::new(StatusCode::BAD_REQUEST, detail)
+    }
+
+    pub fn not_acceptable(detail: String) -> Self {
+        HttpError::new(StatusCode::NOT_ACCEPTABLE, detail)
+    }
+
+    pub fn unsupported_media_type(detail: String) -> Self {
+        HttpError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, detail)
+    }
+
+    pub fn expectation_failed(detail: String) -> Self {
+        HttpError::new(StatusCode::EXPECTATION_FAILED, detail)
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.detail)
+    }
+}
+
+impl Error for HttpError {}
@@ -0,0 +1,162 @@
+/// Header fields of a request or response. Names are compared case-insensitively,
+/// a name can have several values, and fields keep the order they were added in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap { entries: Vec::new() }
+    }
+
+    /// The first value of the header.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.iter().find(|it| it.0.eq_ignore_ascii_case(name)).map(|it| &it.1[..])
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a str> + 'a {
+        self.entries.iter().filter(move |it| it.0.eq_ignore_ascii_case(name)).map(|it| &it.1[..])
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the header to a single value, replacing existing values in place.
+    /// A field with an invalid name is dropped, see `sanitize`.
+    pub fn insert(&mut self, name: String, value: String) {
+        let (name, value) = match sanitize(name, value) {
+            Some(field) => field,
+            None => return,
+        };
+        match self.entries.iter().position(|it| it.0.eq_ignore_ascii_case(&name)) {
+            Some(index) => {
+                let rest = self.entries.split_off(index + 1);
+                self.entries.extend(rest.into_iter().filter(|it| !it.0.eq_ignore_ascii_case(&name)));
+                self.entries[index].1 = value;
+            }
+            None => self.entries.push((name, value)),
+        }
+    }
+
+    /// Adds a value, keeping the values already present under the name.
+    pub fn append(&mut self, name: String, value: String) {
+        self.entries.extend(sanitize(name, value));
+    }
+
+    /// Removes all values of the header, returns the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let first = self.get(name).map(|it| it.to_string());
+        self.entries.retain(|it| !it.0.eq_ignore_ascii_case(name));
+        first
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.entries.iter().map(|it| (&it.0[..], &it.1[..]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl FromIterator<(String, String)> for HeaderMap {
+    fn from_iter<T: IntoIterator<Item=(String, String)>>(iter: T) -> Self {
+        HeaderMap { entries: iter.into_iter().filter_map(|(name, value)| sanitize(name, value)).collect() }
+    }
+}
+
+impl Extend<(String, String)> for HeaderMap {
+    fn extend<T: IntoIterator<Item=(String, String)>>(&mut self, iter: T) {
+        self.entries.extend(iter.into_iter().filter_map(|(name, value)| sanitize(name, value)))
+    }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// Whether the byte may appear in a token, e.g. a header name (RFC 9110 section 5.6.2).
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// a CR or LF copied from a request into a header would end the field early and let the
+// rest be read as further headers or a body, so a field whose name isn't a token is dropped
+// and control characters in values become spaces
+fn sanitize(name: String, mut value: String) -> Option<(String, String)> {
+    if name.is_empty() || !name.bytes().all(is_tchar) {
+        eprintln!("header with invalid name dropped: {:?}", name);
+        return None;
+    }
+    if value.chars().any(|it| it.is_ascii_control() && it != '\t') {
+        value = value.chars().map(|it| if it.is_ascii_control() && it != '\t' { ' ' } else { it }).collect();
+    }
+    Some((name, value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut headers = HeaderMap::new();
+        headers.insert(String::from("Content-Length"), String::from("10"));
+        assert_eq!(headers.get("content-length"), Some("10"));
+        assert!(headers.contains_key("CONTENT-LENGTH"));
+        headers.insert(String::from("content-length"), String::from("20"));
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers.get("Content-Length"), Some("20"));
+    }
+
+    #[test]
+    fn test_multiple_values() {
+        let mut headers = HeaderMap::new();
+        headers.append(String::from("Set-Cookie"), String::from("a=1"));
+        headers.append(String::from("Server"), String::from("test"));
+        headers.append(String::from("set-cookie"), String::from("b=2"));
+        assert_eq!(headers.get("Set-Cookie"), Some("a=1"));
+        assert_eq!(headers.get_all("Set-Cookie").collect::<Vec<_>>(), vec!["a=1", "b=2"]);
+
+        headers.insert(String::from("Set-Cookie"), String::from("c=3"));
+        assert_eq!(headers.iter().collect::<Vec<_>>(), vec![("Set-Cookie", "c=3"), ("Server", "test")]);
+
+        assert_eq!(headers.remove("SET-COOKIE"), Some(String::from("c=3")));
+        assert_eq!(headers.remove("Set-Cookie"), None);
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn test_insertion_order() {
+        let headers: HeaderMap = ["C", "A", "B"].iter().map(|it| (it.to_string(), String::new())).collect();
+        assert_eq!(headers.iter().map(|it| it.0).collect::<Vec<_>>(), vec!["C", "A", "B"]);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let mut headers = HeaderMap::new();
+        headers.insert(String::from("Location"), String::from("/a\r\nSet-Cookie: admin=1"));
+        headers.append(String::from("X-Good"), String::from("a\0b\tc"));
+        assert_eq!(headers.iter().collect::<Vec<_>>(), vec![("Location", "/a  Set-Cookie: admin=1"), ("X-Good", "a b\tc")]);
+
+        // a name that isn't a token is never altered or sent empty, the field is dropped
+        headers.append(String::from("X-Bad\r\nName"), String::from("1"));
+        headers.insert(String::from("X Bad:"), String::from("1"));
+        headers.insert(String::from("\r\n"), String::from("1"));
+        headers.append(String::new(), String::from("1"));
+        assert_eq!(headers.len(), 2);
+        let parsed: HeaderMap = vec![(String::from("X-A"), String::from("1\n2"))].into_iter().collect();
+        assert_eq!(parsed.get("X-A"), Some("1 2"));
+    }
+}
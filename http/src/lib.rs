@@ -1,6 +1,26 @@
+pub mod header;
+pub mod cookie;
+pub mod date;
+pub mod media_type;
+pub mod accept;
+pub mod multipart;
 pub mod request;
+pub mod path;
+pub mod reader;
+pub mod limits;
+pub mod chunked;
+pub mod body;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "templates")]
+pub mod templates;
 pub mod response;
+pub mod status;
 pub mod router;
 pub mod static_mapping;
 pub mod channel;
+pub mod sse;
+pub mod websocket;
+pub mod connection;
 pub mod utils;
+pub mod error;
@@ -0,0 +1,24 @@
+/// Size limits applied while a request is read. Exceeding them is answered with
+/// 414 URI Too Long, 431 Request Header Fields Too Large or 413 Payload Too Large.
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    // bytes in the request line, without the line break
+    pub max_request_line: usize,
+    // number of header fields
+    pub max_headers: usize,
+    // bytes of all header lines together
+    pub max_header_size: usize,
+    // bytes of the decoded body, routes can override it
+    pub max_body_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_request_line: 8 * 1024,
+            max_headers: 100,
+            max_header_size: 16 * 1024,
+            max_body_size: 8 * 1024 * 1024,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+/// A parsed `Content-Type` style value, e.g. `text/html; charset=utf-8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaType {
+    // lowercase `type/subtype`
+    pub mime: String,
+    // lowercase names, unquoted values
+    pub params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    pub fn parse(s: &str) -> Option<MediaType> {
+        let mut parts = split_unquoted(s, ';').into_iter();
+        let mime = parts.next()?.trim().to_ascii_lowercase();
+        match mime.split_once('/') {
+            Some((main, sub)) if !main.is_empty() && !sub.is_empty() => {}
+            _ => return None,
+        }
+        let params = parts.filter_map(|it| {
+            let (name, value) = it.split_once('=')?;
+            Some((name.trim().to_ascii_lowercase(), unquote(value.trim())))
+        }).collect();
+        Some(MediaType { mime, params })
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|it| it.0.eq_ignore_ascii_case(name)).map(|it| &it.1[..])
+    }
+}
+
+/// Splits on the separator, except inside quoted strings.
+pub(crate) fn split_unquoted(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, ch) in s.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if ch == separator && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+pub(crate) fn unquote(s: &str) -> String {
+    match s.strip_prefix('"').and_then(|it| it.strip_suffix('"')) {
+        Some(quoted) => {
+            let mut value = String::new();
+            let mut chars = quoted.chars();
+            while let Some(ch) = chars.next() {
+                value.push(if ch == '\\' { chars.next().unwrap_or(ch) } else { ch });
+            }
+            value
+        }
+        None => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let media_type = MediaType::parse("Text/HTML; Charset=\"UTF-8\"").unwrap();
+        assert_eq!(media_type.mime, "text/html");
+        assert_eq!(media_type.param("charset"), Some("UTF-8"));
+
+        let media_type = MediaType::parse("multipart/form-data; boundary=\"a;b\\\"c\"; x=1").unwrap();
+        assert_eq!(media_type.param("boundary"), Some("a;b\"c"));
+        assert_eq!(media_type.param("x"), Some("1"));
+
+        assert_eq!(MediaType::parse("application/json").unwrap().params, vec![]);
+        assert_eq!(MediaType::parse("json"), None);
+        assert_eq!(MediaType::parse("/json"), None);
+    }
+}
@@ -0,0 +1,388 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use percent_encoding::percent_decode_str;
+use crate::header::HeaderMap;
+use crate::media_type::{split_unquoted, unquote};
+use crate::request::{parse_header, ParseError};
+
+// longest part header line and most header lines accepted per part
+const MAX_HEADER_LINE: usize = 8 * 1024;
+const MAX_PART_HEADERS: usize = 32;
+
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+pub struct MultipartLimits {
+    // bytes of a single part
+    pub max_part_size: usize,
+    // bytes of all parts together
+    pub max_total_size: usize,
+    // file parts growing beyond this many bytes are moved to a temp file
+    pub memory_threshold: usize,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        // the same as the default `RequestLimits::max_body_size`, routes taking larger
+        // uploads raise both
+        MultipartLimits {
+            max_part_size: 8 * 1024 * 1024,
+            max_total_size: 8 * 1024 * 1024,
+            memory_threshold: 256 * 1024,
+        }
+    }
+}
+
+/// A file in the temp directory holding a file part, removed when dropped.
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create() -> io::Result<(TempFile, File)> {
+        let name = format!("rust-web-server-{}-{}.part", process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok((TempFile { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Moves the file to its final place, so it is kept.
+    pub fn persist<P: AsRef<Path>>(self, to: P) -> io::Result<()> {
+        if fs::rename(&self.path, to.as_ref()).is_err() {
+            // rename fails across file systems
+            fs::copy(&self.path, to.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub enum PartData {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+#[derive(Debug)]
+pub struct Part {
+    pub headers: HeaderMap,
+    // the `name` of the form field
+    pub name: String,
+    // set for file fields
+    pub filename: Option<String>,
+    pub data: PartData,
+    size: usize,
+}
+
+impl Part {
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get("Content-Type")
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The content when it is held in memory.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match &self.data {
+            PartData::Memory(data) => Some(data),
+            PartData::File(_) => None,
+        }
+    }
+
+    /// The content of an in-memory part as text, e.g. a text field.
+    pub fn text(&self) -> Option<&str> {
+        self.bytes().and_then(|it| str::from_utf8(it).ok())
+    }
+
+    /// Reads the content wherever it is kept.
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match &self.data {
+            PartData::Memory(data) => Ok(Box::new(&data[..])),
+            PartData::File(file) => Ok(Box::new(file.open()?)),
+        }
+    }
+}
+
+/// Streaming `multipart/form-data` parser. Parts are read one at a time from the reader,
+/// file parts over the memory threshold are written to temp files as they arrive.
+pub struct Multipart<R: Read> {
+    reader: R,
+    // CRLF, two dashes and the boundary
+    delimiter: Vec<u8>,
+    limits: MultipartLimits,
+    // read but not yet parsed bytes
+    buffer: Vec<u8>,
+    total_size: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> Multipart<R> {
+    pub fn new(reader: R, boundary: &str, limits: MultipartLimits) -> Self {
+        let delimiter = format!("\r\n--{}", boundary).into_bytes();
+        // the first delimiter is not preceded by a line break
+        Multipart { reader, delimiter, limits, buffer: b"\r\n".to_vec(), total_size: 0, started: false, finished: false }
+    }
+
+    /// Returns `None` after the last part.
+    pub fn next_part(&mut self) -> Result<Option<Part>, ParseError> {
+        if self.finished {
+            return Ok(None);
+        }
+        if !self.started {
+            // skips the preamble
+            self.read_data(|_| Ok(()))?;
+            self.started = true;
+        }
+        if self.fill(2)? >= 2 && self.buffer.starts_with(b"--") {
+            self.finished = true;
+            // the epilogue is ignored, but read so a body streamed from the connection ends
+            io::copy(&mut self.reader, &mut io::sink())?;
+            return Ok(None);
+        }
+        // the rest of the delimiter line is transport padding
+        self.read_line()?;
+        let headers = self.read_headers()?;
+        let disposition = headers.get("Content-Disposition")
+            .ok_or_else(|| invalid("part without Content-Disposition"))?;
+        let (name, filename) = parse_content_disposition(disposition);
+        let name = name.ok_or_else(|| invalid("part without a name"))?;
+
+        let limits = self.limits.clone();
+        let is_file = filename.is_some();
+        let mut memory = Vec::new();
+        let mut file: Option<(TempFile, File)> = None;
+        let mut size = 0;
+        let total_size = self.total_size;
+        self.read_data(|data| {
+            size += data.len();
+            if size > limits.max_part_size || total_size + size > limits.max_total_size {
+                return Err(ParseError::PayloadTooLarge);
+            }
+            if is_file && file.is_none() && size > limits.memory_threshold {
+                let (temp_file, mut f) = TempFile::create()?;
+                f.write_all(&memory)?;
+                memory = Vec::new();
+                file = Some((temp_file, f));
+            }
+            match &mut file {
+                Some((_, f)) => f.write_all(data)?,
+                None => memory.extend_from_slice(data),
+            }
+            Ok(())
+        })?;
+        self.total_size += size;
+        let data = match file {
+            Some((temp_file, mut f)) => {
+                f.flush()?;
+                PartData::File(temp_file)
+            }
+            None => PartData::Memory(memory),
+        };
+        Ok(Some(Part { headers, name, filename, data, size }))
+    }
+
+    /// Passes the data up to the next delimiter to the sink and consumes the delimiter.
+    fn read_data<F>(&mut self, mut sink: F) -> Result<(), ParseError>
+        where F: FnMut(&[u8]) -> Result<(), ParseError>
+    {
+        loop {
+            if let Some(index) = find(&self.buffer, &self.delimiter) {
+                sink(&self.buffer[..index])?;
+                self.buffer.drain(..index + self.delimiter.len());
+                return Ok(());
+            }
+            // the end of the buffer may be the start of a delimiter
+            let keep = self.buffer.len().min(self.delimiter.len() - 1);
+            let emit = self.buffer.len() - keep;
+            sink(&self.buffer[..emit])?;
+            self.buffer.drain(..emit);
+            if self.read_more()? == 0 {
+                return Err(invalid("multipart body ended before the closing boundary"));
+            }
+        }
+    }
+
+    fn read_headers(&mut self) -> Result<HeaderMap, ParseError> {
+        let mut headers = HeaderMap::new();
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                return Ok(headers);
+            }
+            if headers.len() == MAX_PART_HEADERS {
+                return Err(ParseError::HeaderTooLarge);
+            }
+            let (name, value) = parse_header(&String::from_utf8_lossy(&line))?;
+            headers.append(name, value);
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, ParseError> {
+        let mut start = 0;
+        loop {
+            if let Some(index) = self.buffer[start..].iter().position(|it| *it == b'\n') {
+                let mut line: Vec<u8> = self.buffer.drain(..start + index + 1).collect();
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                return Ok(line);
+            }
+            if self.buffer.len() > MAX_HEADER_LINE {
+                return Err(ParseError::HeaderTooLarge);
+            }
+            start = self.buffer.len();
+            if self.read_more()? == 0 {
+                return Err(invalid("multipart body ended inside the part headers"));
+            }
+        }
+    }
+
+    /// Reads until the buffer holds `n` bytes or the input ends, returns the buffered length.
+    fn fill(&mut self, n: usize) -> Result<usize, ParseError> {
+        while self.buffer.len() < n && self.read_more()? > 0 {}
+        Ok(self.buffer.len())
+    }
+
+    fn read_more(&mut self) -> Result<usize, ParseError> {
+        let mut chunk = [0; 8192];
+        let n = self.reader.read(&mut chunk)?;
+        self.buffer.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+}
+
+fn invalid(message: &str) -> ParseError {
+    ParseError::InvalidBody(String::from(message))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|it| it == needle)
+}
+
+/// Returns the `name` and `filename` of a `form-data` disposition, `filename*` wins over `filename`.
+fn parse_content_disposition(value: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut filename = None;
+    let mut extended_filename = None;
+    for param in split_unquoted(value, ';').into_iter().skip(1) {
+        let (key, value) = match param.split_once('=') {
+            Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        match key.as_str() {
+            "name" => name = Some(unquote(value)),
+            "filename" => filename = Some(unquote(value)),
+            // RFC 5987 form: charset'language'percent-encoded
+            "filename*" => extended_filename = value.splitn(3, '\'').nth(2)
+                .map(|it| percent_decode_str(it).decode_utf8_lossy().into_owned()),
+            _ => {}
+        }
+    }
+    (name, extended_filename.or(filename))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BODY: &str = "preamble\r
+--WebAppBoundary\r
+Content-Disposition: form-data; name=\"element-name\"\r
+Content-Type: text/plain\r
+\r
+Name\r
+--WebAppBoundary\r
+Content-Disposition: form-data; name=\"data\"; filename=\"data.json\"\r
+Content-Type: application/json\r
+\r
+{\"name\": \"my-name\"}\r
+--WebAppBoundary--\r
+";
+
+    #[test]
+    fn test_parts() {
+        let mut multipart = Multipart::new(BODY.as_bytes(), "WebAppBoundary", MultipartLimits::default());
+        let field = multipart.next_part().unwrap().unwrap();
+        assert_eq!(field.name, "element-name");
+        assert!(!field.is_file());
+        assert_eq!(field.text(), Some("Name"));
+
+        let file = multipart.next_part().unwrap().unwrap();
+        assert_eq!(file.name, "data");
+        assert_eq!(file.filename.as_deref(), Some("data.json"));
+        assert_eq!(file.content_type(), Some("application/json"));
+        assert_eq!(file.text(), Some("{\"name\": \"my-name\"}"));
+
+        assert!(multipart.next_part().unwrap().is_none());
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_file_part_in_temp_file() {
+        let content = "0123456789".repeat(10000);
+        let body = format!("--b\r\nContent-Disposition: form-data; name=\"f\"; filename*=UTF-8''%E6%8A%A5%E5%91%8A.csv\r\n\r\n{}\r\n--b--", content);
+        let limits = MultipartLimits { memory_threshold: 1024, ..Default::default() };
+        let mut multipart = Multipart::new(body.as_bytes(), "b", limits);
+        let part = multipart.next_part().unwrap().unwrap();
+        assert_eq!(part.filename.as_deref(), Some("报告.csv"));
+        assert_eq!(part.size(), content.len());
+        assert!(part.bytes().is_none());
+        let path = match &part.data {
+            PartData::File(file) => file.path().to_path_buf(),
+            PartData::Memory(_) => panic!("expected a temp file"),
+        };
+        let mut read = String::new();
+        part.reader().unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!(read, content);
+        drop(part);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = MultipartLimits { max_part_size: 3, ..Default::default() };
+        let mut multipart = Multipart::new(BODY.as_bytes(), "WebAppBoundary", limits);
+        assert!(matches!(multipart.next_part(), Err(ParseError::PayloadTooLarge)));
+
+        let limits = MultipartLimits { max_total_size: 10, ..Default::default() };
+        let mut multipart = Multipart::new(BODY.as_bytes(), "WebAppBoundary", limits);
+        assert!(multipart.next_part().is_ok());
+        assert!(matches!(multipart.next_part(), Err(ParseError::PayloadTooLarge)));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut multipart = Multipart::new("--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nno end".as_bytes(),
+                                           "b", MultipartLimits::default());
+        assert!(matches!(multipart.next_part(), Err(ParseError::InvalidBody(_))));
+        let mut multipart = Multipart::new("--b\r\nContent-Type: text/plain\r\n\r\nx\r\n--b--".as_bytes(),
+                                           "b", MultipartLimits::default());
+        assert!(matches!(multipart.next_part(), Err(ParseError::InvalidBody(_))));
+    }
+}
@@ -0,0 +1,90 @@
+use percent_encoding::percent_decode_str;
+use crate::request::ParseError;
+
+/// How percent-encoded characters that could change the meaning of a path are treated.
+/// Allowed encoded separators stay encoded in the normalized path, so `/a%2Fb` never
+/// matches a route for `/a/b`.
+#[derive(Debug, Clone)]
+pub struct PathPolicy {
+    // reject `%2F` and `%5C` instead of keeping them encoded
+    pub reject_encoded_separators: bool,
+    // reject `%00` instead of keeping it encoded
+    pub reject_nul: bool,
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        PathPolicy { reject_encoded_separators: true, reject_nul: true }
+    }
+}
+
+/// Percent-decodes the path, removes `.` and `..` segments and collapses repeated slashes.
+/// A trailing slash is kept, `..` never leaves the root, and `%` itself stays encoded as `%25`.
+pub fn normalize_path(raw_path: &str, policy: &PathPolicy) -> Result<String, ParseError> {
+    let invalid_uri = || ParseError::InvalidUri(raw_path.to_string());
+    if !raw_path.starts_with('/') {
+        return Err(invalid_uri());
+    }
+    let mut segments: Vec<String> = Vec::new();
+    let raw_segments: Vec<&str> = raw_path[1..].split('/').collect();
+    for (i, raw_segment) in raw_segments.iter().enumerate() {
+        let segment = percent_decode_str(raw_segment).decode_utf8().map_err(|_| invalid_uri())?;
+        let is_last = i == raw_segments.len() - 1;
+        match &segment[..] {
+            "" | "." => {}
+            ".." => { segments.pop(); }
+            _ => segments.push(encode_reserved(&segment, policy).ok_or_else(invalid_uri)?),
+        }
+        // a trailing slash, or a last segment that was removed, leaves the path ending in `/`
+        if is_last && matches!(&segment[..], "" | "." | "..") && !segments.is_empty() {
+            segments.push(String::new());
+        }
+    }
+    Ok(format!("/{}", segments.join("/")))
+}
+
+// encodes the characters the policy allows but which must not appear decoded
+fn encode_reserved(segment: &str, policy: &PathPolicy) -> Option<String> {
+    let mut encoded = String::with_capacity(segment.len());
+    for ch in segment.chars() {
+        match ch {
+            '/' | '\\' if policy.reject_encoded_separators => return None,
+            '\0' if policy.reject_nul => return None,
+            '/' => encoded.push_str("%2F"),
+            '\\' => encoded.push_str("%5C"),
+            '\0' => encoded.push_str("%00"),
+            '%' => encoded.push_str("%25"),
+            _ => encoded.push(ch),
+        }
+    }
+    Some(encoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        let normalize = |s: &str| normalize_path(s, &PathPolicy::default()).ok();
+        assert_eq!(normalize("/"), Some(String::from("/")));
+        assert_eq!(normalize("/a//b///c"), Some(String::from("/a/b/c")));
+        assert_eq!(normalize("/a/./b/../c/"), Some(String::from("/a/c/")));
+        assert_eq!(normalize("/a/b/.."), Some(String::from("/a/")));
+        assert_eq!(normalize("/../../etc/passwd"), Some(String::from("/etc/passwd")));
+        assert_eq!(normalize("/a/%2e%2E/b"), Some(String::from("/b")));
+        assert_eq!(normalize("/caf%C3%A9%20x"), Some(String::from("/café x")));
+        assert_eq!(normalize("/100%25"), Some(String::from("/100%25")));
+        assert_eq!(normalize("/a%2Fb"), None);
+        assert_eq!(normalize("/a%5cb"), None);
+        assert_eq!(normalize("/a%00"), None);
+        assert_eq!(normalize("/%FF"), None);
+        assert_eq!(normalize("a"), None);
+    }
+
+    #[test]
+    fn test_lenient_policy() {
+        let policy = PathPolicy { reject_encoded_separators: false, reject_nul: false };
+        assert_eq!(normalize_path("/a%2Fb/%5C/%00", &policy).unwrap(), "/a%2Fb/%5C/%00");
+    }
+}
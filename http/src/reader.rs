@@ -0,0 +1,388 @@
+use std::io::{self, BufRead, BufReader, Read};
+use crate::chunked::{decode_chunked, is_chunked, ChunkedReader};
+use crate::connection::ConnectionInfo;
+use crate::limits::RequestLimits;
+use crate::path::PathPolicy;
+use crate::request::{check_host, HttpRequest, ParseError};
+
+/// Reads requests from a byte stream. The request head is read up to the blank line,
+/// then the body follows either chunked or as exactly `Content-Length` bytes.
+pub struct HttpReader<R: Read> {
+    reader: BufReader<R>,
+    limits: RequestLimits,
+    path_policy: PathPolicy,
+    connection: ConnectionInfo,
+}
+
+impl<R: Read> HttpReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_limits(inner, RequestLimits::default())
+    }
+
+    pub fn with_limits(inner: R, limits: RequestLimits) -> Self {
+        Self { reader: BufReader::new(inner), limits, path_policy: PathPolicy::default(), connection: ConnectionInfo::default() }
+    }
+
+    /// Sets the connection the requests are read from, each request gets it with its sequence number.
+    pub fn connection(&mut self, connection: ConnectionInfo) -> &mut Self {
+        self.connection = connection;
+        self
+    }
+
+    pub fn path_policy(&mut self, path_policy: PathPolicy) -> &mut Self {
+        self.path_policy = path_policy;
+        self
+    }
+
+    /// Reads the head and the body of the next request.
+    /// Returns `None` when the peer closed the connection before sending a request.
+    pub fn read_request(&mut self) -> Result<Option<HttpRequest>, ParseError> {
+        let mut http_request = match self.read_head()? {
+            Some(http_request) => http_request,
+            None => return Ok(None),
+        };
+        self.read_body(&mut http_request, self.limits.max_body_size)?;
+        Ok(Some(http_request))
+    }
+
+    /// Reads the request line and the headers only, the body has to be read with `read_body`.
+    /// Returns `None` when the peer closed the connection before sending a request.
+    pub fn read_head(&mut self) -> Result<Option<HttpRequest>, ParseError> {
+        match self.read_head_bytes()? {
+            Some(head) => {
+                self.connection.sequence += 1;
+                let mut http_request = HttpRequest::with_path_policy(&head, &self.path_policy)?;
+                http_request.connection = self.connection.clone();
+                check_host(&http_request)?;
+                Ok(Some(http_request))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Checks the framing of the body and its declared length, without reading it. Lets a
+    /// request be rejected before the client is told to send its body.
+    pub fn check_body(&self, http_request: &HttpRequest, max_body_size: usize) -> Result<(), ParseError> {
+        match body_length(http_request)? {
+            Some(length) if length > max_body_size => Err(ParseError::PayloadTooLarge),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read_body(&mut self, http_request: &mut HttpRequest, max_body_size: usize) -> Result<(), ParseError> {
+        self.check_body(http_request, max_body_size)?;
+        let length = match body_length(http_request)? {
+            Some(length) => length,
+            None => {
+                let (body, trailers) = decode_chunked(&mut self.reader, max_body_size)?;
+                http_request.body = Some(body);
+                http_request.trailers.extend(trailers);
+                return Ok(());
+            }
+        };
+        if length > 0 {
+            let mut body = vec![0; length];
+            self.reader.read_exact(&mut body)?;
+            http_request.body = Some(body);
+        }
+        Ok(())
+    }
+
+    /// The body as a stream read straight from the connection, for routes that process it
+    /// while it arrives, e.g. large uploads. The next request can only be read after it was
+    /// read to the end, see `BodyReader::is_finished`.
+    pub fn body_reader(&mut self, http_request: &HttpRequest, max_body_size: usize) -> Result<BodyReader<'_, R>, ParseError> {
+        self.check_body(http_request, max_body_size)?;
+        Ok(match body_length(http_request)? {
+            Some(length) => BodyReader::Sized((&mut self.reader).take(length as u64)),
+            None => BodyReader::Chunked(ChunkedReader::new(&mut self.reader, max_body_size)),
+        })
+    }
+
+    fn read_head_bytes(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        let mut head = Vec::new();
+        let mut line = Vec::new();
+        let mut header_count = 0;
+        let mut header_size = 0;
+        loop {
+            line.clear();
+            let max_line = if head.is_empty() {
+                self.limits.max_request_line
+            } else {
+                self.limits.max_header_size.saturating_sub(header_size)
+            };
+            // leaves room for the line break
+            let limit = max_line as u64 + 2;
+            let n = (&mut self.reader).take(limit).read_until(b'\n', &mut line)?;
+            if n == 0 && head.is_empty() {
+                return Ok(None);
+            }
+            if !line.ends_with(b"\n") {
+                return Err(match n as u64 {
+                    n if n < limit => io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the end of the request head").into(),
+                    _ if head.is_empty() => ParseError::UriTooLong,
+                    _ => ParseError::HeaderTooLarge,
+                });
+            }
+            let is_empty_line = line == b"\r\n" || line == b"\n";
+            // empty lines before the request line are ignored
+            if head.is_empty() && is_empty_line {
+                continue;
+            }
+            if !head.is_empty() && !is_empty_line {
+                header_count += 1;
+                header_size += line.len();
+                if header_count > self.limits.max_headers {
+                    return Err(ParseError::HeaderTooLarge);
+                }
+            }
+            head.extend_from_slice(&line);
+            if is_empty_line {
+                return Ok(Some(head));
+            }
+        }
+    }
+}
+
+/// A request body read while it arrives, created by `HttpReader::body_reader`.
+pub enum BodyReader<'a, R: Read> {
+    // exactly `Content-Length` bytes
+    Sized(io::Take<&'a mut BufReader<R>>),
+    Chunked(ChunkedReader<&'a mut BufReader<R>>),
+}
+
+impl<R: Read> BodyReader<'_, R> {
+    /// Whether the body was read to the end, so the connection is at the next request.
+    pub fn is_finished(&self) -> bool {
+        match self {
+            BodyReader::Sized(reader) => reader.limit() == 0,
+            BodyReader::Chunked(reader) => reader.is_finished(),
+        }
+    }
+}
+
+impl<R: Read> Read for BodyReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            BodyReader::Sized(reader) => {
+                let n = reader.read(buf)?;
+                if n == 0 && !buf.is_empty() && reader.limit() > 0 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the end of the body"));
+                }
+                Ok(n)
+            }
+            BodyReader::Chunked(reader) => reader.read(buf),
+        }
+    }
+}
+
+// the length of the body, `None` when it is chunked. Framing that servers on the way
+// could read differently is rejected, so they can't disagree on where the next request
+// starts (RFC 9112 section 6.3)
+fn body_length(http_request: &HttpRequest) -> Result<Option<usize>, ParseError> {
+    let content_length = content_length(http_request)?;
+    match http_request.joined_header("Transfer-Encoding") {
+        Some(_) if content_length.is_some() => Err(ParseError::InvalidBody(String::from("both Transfer-Encoding and Content-Length"))),
+        Some(transfer_encoding) if is_chunked(&transfer_encoding) => Ok(None),
+        Some(_) => Err(ParseError::InvalidBody(String::from("unsupported Transfer-Encoding"))),
+        None => Ok(Some(content_length.unwrap_or(0))),
+    }
+}
+
+// every `Content-Length` value has to be the same number, digits only
+fn content_length(http_request: &HttpRequest) -> Result<Option<usize>, ParseError> {
+    let mut length = None;
+    for value in http_request.headers.get_all("Content-Length").flat_map(|it| it.split(',')) {
+        let value = value.trim();
+        let invalid = || ParseError::InvalidBody(format!("invalid Content-Length: {}", value));
+        if value.is_empty() || !value.bytes().all(|it| it.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let value = value.parse().map_err(|_| invalid())?;
+        if length.is_some_and(|it| it != value) {
+            return Err(ParseError::InvalidBody(String::from("conflicting Content-Length values")));
+        }
+        length = Some(value);
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use super::*;
+
+    /// Hands out the input a few bytes at a time, like a stream receiving small segments.
+    struct SegmentedRead<'a> {
+        data: &'a [u8],
+        segment: usize,
+    }
+
+    impl<'a> Read for SegmentedRead<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.segment.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_body_by_content_length() {
+        let body = "x".repeat(20000);
+        let s = format!("POST /post HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+        let mut reader = HttpReader::new(SegmentedRead { data: s.as_bytes(), segment: 7 });
+        let http_request = reader.read_request().unwrap().unwrap();
+        assert_eq!(http_request.path, "/post");
+        assert_eq!(http_request.body_str(), Some(&body[..]));
+        assert!(reader.read_request().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_pipelined_requests() {
+        let s = "POST /a HTTP/1.1\r\nHost: a\r\ncontent-length: 5\r\n\r\nhelloGET /b HTTP/1.1\r\nHost: a\r\n\r\n";
+        let mut reader = HttpReader::new(s.as_bytes());
+        let first = reader.read_request().unwrap().unwrap();
+        assert_eq!(first.path, "/a");
+        assert_eq!(first.body_str(), Some("hello"));
+        let second = reader.read_request().unwrap().unwrap();
+        assert_eq!(second.path, "/b");
+        assert_eq!(second.body(), None);
+    }
+
+    #[test]
+    fn test_read_chunked_body() {
+        let s = "POST /a HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+                 4\r\nWiki\r\n5\r\npedia\r\n0\r\nX-Sum: 1\r\n\r\nGET /b HTTP/1.1\r\nHost: a\r\n\r\n";
+        let mut reader = HttpReader::new(SegmentedRead { data: s.as_bytes(), segment: 3 });
+        let first = reader.read_request().unwrap().unwrap();
+        assert_eq!(first.body_str(), Some("Wikipedia"));
+        assert_eq!(first.trailer("X-Sum"), Some("1"));
+        assert_eq!(reader.read_request().unwrap().unwrap().path, "/b");
+    }
+
+    #[test]
+    fn test_read_binary_body() {
+        let mut s = b"POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\n\r\n".to_vec();
+        s.extend_from_slice(&[0x1f, 0x8b, 0x08, 0xff]);
+        let mut reader = HttpReader::new(&s[..]);
+        let http_request = reader.read_request().unwrap().unwrap();
+        assert_eq!(http_request.body(), Some(&[0x1f, 0x8b, 0x08, 0xff][..]));
+    }
+
+    #[test]
+    fn test_truncated_request() {
+        let mut reader = HttpReader::new("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nhello".as_bytes());
+        assert!(matches!(reader.read_request(), Err(ParseError::Io(_))));
+        let mut reader = HttpReader::new("GET /a HTTP/1.1\r\nHost: a".as_bytes());
+        assert!(matches!(reader.read_request(), Err(ParseError::Io(_))));
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = RequestLimits { max_request_line: 24, max_headers: 2, max_header_size: 40, max_body_size: 10 };
+        let read = |s: &str| HttpReader::with_limits(s.as_bytes(), limits.clone()).read_request();
+
+        assert!(read("GET /aaaaaaaaaa HTTP/1.0\r\n\r\n").is_ok());
+        assert!(matches!(read("GET /aaaaaaaaaaa HTTP/1.0\r\n\r\n"), Err(ParseError::UriTooLong)));
+        assert!(matches!(read(&format!("GET /{} HTTP/1.0\r\n\r\n", "a".repeat(100000))), Err(ParseError::UriTooLong)));
+        assert!(matches!(read("GET / HTTP/1.0\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"), Err(ParseError::HeaderTooLarge)));
+        assert!(matches!(read(&format!("GET / HTTP/1.0\r\nA: {}\r\n\r\n", "1".repeat(40))), Err(ParseError::HeaderTooLarge)));
+        assert!(read("POST / HTTP/1.0\r\nContent-Length: 10\r\n\r\n0123456789").is_ok());
+        assert!(matches!(read("POST / HTTP/1.0\r\nContent-Length: 11\r\n\r\n01234567890"), Err(ParseError::PayloadTooLarge)));
+        assert!(matches!(read("POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n012345\r\n6\r\n012345\r\n0\r\n\r\n"),
+                         Err(ParseError::PayloadTooLarge)));
+    }
+
+    #[test]
+    fn test_read_body_with_route_limit() {
+        let mut reader = HttpReader::new("POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello".as_bytes());
+        let mut http_request = reader.read_head().unwrap().unwrap();
+        assert_eq!(http_request.body(), None);
+        assert!(matches!(reader.check_body(&http_request, 4), Err(ParseError::PayloadTooLarge)));
+        assert!(matches!(reader.read_body(&mut http_request, 4), Err(ParseError::PayloadTooLarge)));
+        let mut reader = HttpReader::new("POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello".as_bytes());
+        let mut http_request = reader.read_head().unwrap().unwrap();
+        reader.read_body(&mut http_request, 5).unwrap();
+        assert_eq!(http_request.body_str(), Some("hello"));
+    }
+
+    #[test]
+    fn test_invalid_framing() {
+        let mut reader = HttpReader::new("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: ten\r\n\r\n".as_bytes());
+        assert!(matches!(reader.read_request(), Err(ParseError::InvalidBody(_))));
+        let mut reader = HttpReader::new("POST /a HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip\r\n\r\n".as_bytes());
+        assert!(matches!(reader.read_request(), Err(ParseError::InvalidBody(_))));
+    }
+
+    #[test]
+    fn test_smuggling_framing() {
+        let read = |s: &str| HttpReader::new(s.as_bytes()).read_request();
+        assert!(matches!(read("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 50\r\n\r\nhello"),
+                         Err(ParseError::InvalidBody(_))));
+        assert!(matches!(read("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 6\r\n\r\nhello"), Err(ParseError::InvalidBody(_))));
+        assert!(matches!(read("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: +5\r\n\r\nhello"), Err(ParseError::InvalidBody(_))));
+        assert!(matches!(read("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: -0\r\n\r\n"), Err(ParseError::InvalidBody(_))));
+        assert!(matches!(read("POST /a HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n0\r\n\r\n"),
+                         Err(ParseError::InvalidBody(_))));
+        // only the last coding counts, over all the Transfer-Encoding fields
+        assert!(matches!(read("POST /a HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: gzip\r\n\r\n0\r\n\r\n"),
+                         Err(ParseError::InvalidBody(_))));
+        // repeating the same length is allowed
+        let http_request = read("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\ncontent-length: 5, 5\r\n\r\nhello").unwrap().unwrap();
+        assert_eq!(http_request.body_str(), Some("hello"));
+    }
+
+    #[test]
+    fn test_body_reader() {
+        let s = "POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhelloPOST /b HTTP/1.1\r\nHost: a\r\n\
+                 Transfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\nGET /c HTTP/1.1\r\nHost: a\r\n\r\n";
+        let mut reader = HttpReader::new(SegmentedRead { data: s.as_bytes(), segment: 4 });
+        for expected in ["hello", "abc"] {
+            let http_request = reader.read_head().unwrap().unwrap();
+            let mut body = reader.body_reader(&http_request, 10).unwrap();
+            assert!(!body.is_finished());
+            let mut read = String::new();
+            body.read_to_string(&mut read).unwrap();
+            assert_eq!(read, expected);
+            assert!(body.is_finished());
+        }
+        assert_eq!(reader.read_request().unwrap().unwrap().path, "/c");
+
+        let mut reader = HttpReader::new("POST /a HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhel".as_bytes());
+        let http_request = reader.read_head().unwrap().unwrap();
+        assert!(matches!(reader.body_reader(&http_request, 4), Err(ParseError::PayloadTooLarge)));
+        assert!(reader.body_reader(&http_request, 5).unwrap().read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_missing_host() {
+        let mut reader = HttpReader::new("GET /a HTTP/1.1\r\n\r\n".as_bytes());
+        assert!(matches!(reader.read_request(), Err(ParseError::MissingHost)));
+        let mut reader = HttpReader::new("GET /a HTTP/1.0\r\n\r\n".as_bytes());
+        assert!(reader.read_request().is_ok());
+    }
+
+    #[test]
+    fn test_path_policy() {
+        let s = "GET /files/a%2Fb HTTP/1.0\r\n\r\n";
+        assert!(matches!(HttpReader::new(s.as_bytes()).read_request(), Err(ParseError::InvalidUri(_))));
+        let mut reader = HttpReader::new(s.as_bytes());
+        reader.path_policy(PathPolicy { reject_encoded_separators: false, reject_nul: true });
+        let http_request = reader.read_request().unwrap().unwrap();
+        assert_eq!(http_request.path, "/files/a%2Fb");
+    }
+
+    #[test]
+    fn test_connection_info() {
+        let s = "GET /a HTTP/1.1\r\nHost: a\r\n\r\nGET /b HTTP/1.1\r\nHost: a\r\n\r\n";
+        let connection = ConnectionInfo::new(Some("10.0.0.1:50000".parse().unwrap()), Some("10.0.0.2:80".parse().unwrap()));
+        let mut reader = HttpReader::new(s.as_bytes());
+        reader.connection(connection.clone());
+        let first = reader.read_request().unwrap().unwrap();
+        let second = reader.read_request().unwrap().unwrap();
+        assert_eq!(first.connection().peer_addr, connection.peer_addr);
+        assert_eq!(first.connection().id, connection.id);
+        assert_eq!((first.connection().sequence, second.connection().sequence), (1, 2));
+    }
+}
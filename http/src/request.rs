@@ -1,12 +1,37 @@
-use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str;
 use multimap::MultiMap;
+use percent_encoding::percent_decode;
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
 use regex::Regex;
 use url::{Url};
+use crate::chunked::{decode_chunked, is_chunked};
+use crate::connection::ConnectionInfo;
+use crate::cookie::parse_cookie_header;
+use crate::accept::{negotiate_charset, negotiate_encoding, negotiate_language, negotiate_media_type, parse_quality_values, QualityItem};
+use crate::error::HttpError;
+use crate::header::{is_tchar, HeaderMap};
+use crate::media_type::MediaType;
+use crate::multipart::{Multipart, MultipartLimits};
+use crate::path::{normalize_path, PathPolicy};
+use crate::utils::decode_latin1;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum HttpMethod {
     GET,
+    HEAD,
     POST,
+    PUT,
+    DELETE,
+    CONNECT,
+    OPTIONS,
+    TRACE,
+    PATCH,
+    // any other method token, e.g. PROPFIND
+    Extension(String),
     UNDEFINED,
 }
 
@@ -14,72 +39,365 @@ impl From<&str> for HttpMethod {
     fn from(s: &str) -> Self {
         match s {
             "GET" => HttpMethod::GET,
+            "HEAD" => HttpMethod::HEAD,
             "POST" => HttpMethod::POST,
+            "PUT" => HttpMethod::PUT,
+            "DELETE" => HttpMethod::DELETE,
+            "CONNECT" => HttpMethod::CONNECT,
+            "OPTIONS" => HttpMethod::OPTIONS,
+            "TRACE" => HttpMethod::TRACE,
+            "PATCH" => HttpMethod::PATCH,
+            _ if is_token(s) => HttpMethod::Extension(s.to_string()),
             _ => HttpMethod::UNDEFINED
         }
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for HttpMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpMethod::GET => write!(f, "GET"),
+            HttpMethod::HEAD => write!(f, "HEAD"),
+            HttpMethod::POST => write!(f, "POST"),
+            HttpMethod::PUT => write!(f, "PUT"),
+            HttpMethod::DELETE => write!(f, "DELETE"),
+            HttpMethod::CONNECT => write!(f, "CONNECT"),
+            HttpMethod::OPTIONS => write!(f, "OPTIONS"),
+            HttpMethod::TRACE => write!(f, "TRACE"),
+            HttpMethod::PATCH => write!(f, "PATCH"),
+            HttpMethod::Extension(method) => write!(f, "{}", method),
+            HttpMethod::UNDEFINED => write!(f, "UNDEFINED"),
+        }
+    }
+}
+
+/// Checks the RFC 9110 token grammar used by method names.
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(is_tchar)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum HttpVersion {
-    V1,
+    V1_0,
+    V1_1,
+    // a well-formed version with a major version this server does not speak, e.g. HTTP/2.0
+    Unsupported(u8, u8),
     UNDEFINED,
 }
 
 impl From<&str> for HttpVersion {
     fn from(s: &str) -> Self {
-        match s {
-            "HTTP/1.1" => HttpVersion::V1,
-            _ => HttpVersion::UNDEFINED
+        let version = s.strip_prefix("HTTP/").and_then(|it| it.split_once('.'))
+            .filter(|(major, minor)| major.len() == 1 && minor.len() == 1)
+            .and_then(|(major, minor)| Some((major.parse::<u8>().ok()?, minor.parse::<u8>().ok()?)));
+        match version {
+            Some((1, 0)) => HttpVersion::V1_0,
+            // higher minor versions are compatible with the highest one we implement
+            Some((1, _)) => HttpVersion::V1_1,
+            Some((major, minor)) => HttpVersion::Unsupported(major, minor),
+            None => HttpVersion::UNDEFINED
         }
     }
 }
 
-impl ToString for HttpVersion {
-    fn to_string(&self) -> String {
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            HttpVersion::V1 => String::from("HTTP/1.1"),
-            _ => String::from("UNDEFINED")
+            HttpVersion::V1_0 => write!(f, "HTTP/1.0"),
+            HttpVersion::V1_1 => write!(f, "HTTP/1.1"),
+            HttpVersion::Unsupported(major, minor) => write!(f, "HTTP/{}.{}", major, minor),
+            HttpVersion::UNDEFINED => write!(f, "UNDEFINED")
         }
     }
 }
 
-fn parse_header(value: &str) -> Result<(String, String), &'static str> {
-    if let Some(index) = value.find(':') {
-        Ok((value[..index].trim_end().into(), value[index + 1..].trim_start().into(), ))
-    } else {
-        Err("The header parse error")
+#[derive(Debug)]
+pub enum ParseError {
+    // the request line is not `method SP request-target SP HTTP-version`
+    BadRequestLine(String),
+    // a header line without a colon, with an invalid name or an obsolete line folding
+    BadHeader(String),
+    InvalidUri(String),
+    // an HTTP/1.1 request without a Host header, or with several
+    MissingHost,
+    // the request line exceeds the configured limit
+    UriTooLong,
+    // too many header fields, or the header section exceeds the configured size
+    HeaderTooLarge,
+    // the body exceeds the configured size
+    PayloadTooLarge,
+    UnsupportedVersion(String),
+    // invalid Content-Length, Transfer-Encoding or chunked framing
+    InvalidBody(String),
+    // the connection failed or was closed in the middle of a request
+    Io(io::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::BadRequestLine(line) => write!(f, "bad request line: {}", line),
+            ParseError::BadHeader(line) => write!(f, "bad header: {}", line),
+            ParseError::InvalidUri(uri) => write!(f, "invalid uri: {}", uri),
+            ParseError::MissingHost => write!(f, "missing or repeated Host header"),
+            ParseError::UriTooLong => write!(f, "uri too long"),
+            ParseError::HeaderTooLarge => write!(f, "header fields too large"),
+            ParseError::PayloadTooLarge => write!(f, "payload too large"),
+            ParseError::UnsupportedVersion(version) => write!(f, "unsupported version: {}", version),
+            ParseError::InvalidBody(message) => write!(f, "invalid body: {}", message),
+            ParseError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        // a ParseError passed through a reader, e.g. from a chunked body read as it arrives
+        if !e.get_ref().is_some_and(|it| it.is::<ParseError>()) {
+            return ParseError::Io(e);
+        }
+        match e.into_inner().map(|it| it.downcast::<ParseError>()) {
+            Some(Ok(e)) => *e,
+            _ => unreachable!("the inner error is a ParseError"),
+        }
+    }
+}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// The request target as sent in the request line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestTarget {
+    // `/path?query`, the usual form
+    Origin(String),
+    // `http://host/path?query`, as sent to proxies
+    Absolute(String),
+    // `host:port`, only for CONNECT
+    Authority(String),
+    // `*`, only for server-wide OPTIONS
+    Asterisk,
+}
+
+impl fmt::Display for RequestTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestTarget::Origin(target)
+            | RequestTarget::Absolute(target)
+            | RequestTarget::Authority(target) => write!(f, "{}", target),
+            RequestTarget::Asterisk => write!(f, "*"),
+        }
+    }
+}
+
+// authority of the effective request URI when an HTTP/1.0 request has no Host header
+const DEFAULT_HOST: &str = "localhost";
+
+fn parse_target(method: &HttpMethod, target: String) -> Result<RequestTarget, ParseError> {
+    if *method == HttpMethod::CONNECT {
+        return match target.rsplit_once(':') {
+            // the port is digits only, `parse` would also take a sign
+            Some((host, port)) if !host.is_empty() && !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit())
+                && port.parse::<u16>().is_ok() && !target.contains(['/', '?', '#', '@']) => {
+                Ok(RequestTarget::Authority(target))
+            }
+            _ => Err(ParseError::InvalidUri(target)),
+        };
+    }
+    if target.starts_with('/') {
+        return Ok(RequestTarget::Origin(target));
+    }
+    if target == "*" && *method == HttpMethod::OPTIONS {
+        return Ok(RequestTarget::Asterisk);
+    }
+    match Url::parse(&target) {
+        Ok(url) if (url.scheme() == "http" || url.scheme() == "https") && url.has_host() => Ok(RequestTarget::Absolute(target)),
+        _ => Err(ParseError::InvalidUri(target)),
+    }
+}
+
+/// Builds the URI the client meant, from the target and the `Host` header (RFC 9112 3.3).
+fn effective_uri(target: &RequestTarget, host: Option<&str>) -> Result<Url, ParseError> {
+    let invalid_uri = || ParseError::InvalidUri(target.to_string());
+    match target {
+        RequestTarget::Absolute(uri) => Url::parse(uri).map_err(|_| invalid_uri()),
+        RequestTarget::Authority(authority) => Url::parse(&format!("http://{}", authority)).map_err(|_| invalid_uri()),
+        RequestTarget::Origin(_) | RequestTarget::Asterisk => {
+            let host = host.filter(|it| !it.is_empty()).unwrap_or(DEFAULT_HOST);
+            let invalid_host = || ParseError::BadHeader(format!("Host: {}", host));
+            if host.contains(['/', '?', '#', '@', '\\']) {
+                return Err(invalid_host());
+            }
+            let base = Url::parse(&format!("http://{}", host)).map_err(|_| invalid_host())?;
+            match target {
+                RequestTarget::Origin(path) => Url::parse(&format!("{}{}", base.origin().ascii_serialization(), path))
+                    .map_err(|_| invalid_uri()),
+                _ => Ok(base),
+            }
+        }
+    }
+}
+
+fn strip_query(target: &str) -> &str {
+    target.split(['?', '#']).next().unwrap_or(target)
+}
+
+/// HTTP/1.1 requests must carry exactly one `Host` header.
+pub(crate) fn check_host(http_request: &HttpRequest) -> Result<(), ParseError> {
+    let host_count = http_request.headers.get_all("Host").count();
+    if host_count > 1 || (host_count == 0 && http_request.version == HttpVersion::V1_1) {
+        return Err(ParseError::MissingHost);
+    }
+    Ok(())
+}
+
+fn parse_request_line(line: &str) -> Result<(HttpMethod, String, HttpVersion), ParseError> {
+    let bad_request_line = || ParseError::BadRequestLine(line.to_string());
+    let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+    if parts.len() != 3 {
+        return Err(bad_request_line());
+    }
+    let method = HttpMethod::from(parts[0]);
+    if method == HttpMethod::UNDEFINED {
+        return Err(bad_request_line());
+    }
+    match HttpVersion::from(parts[2]) {
+        HttpVersion::UNDEFINED => Err(bad_request_line()),
+        HttpVersion::Unsupported(..) => Err(ParseError::UnsupportedVersion(parts[2].to_string())),
+        version => Ok((method, parts[1].to_string(), version)),
+    }
+}
+
+pub(crate) fn parse_header(value: &str) -> Result<(String, String), ParseError> {
+    match value.find(':') {
+        Some(index) if is_token(&value[..index]) => {
+            Ok((value[..index].into(), value[index + 1..].trim_matches(|it| it == ' ' || it == '\t').into()))
+        }
+        _ => Err(ParseError::BadHeader(value.to_string())),
     }
 }
 
 #[derive(Debug)]
 pub struct HttpRequest {
     pub method: HttpMethod,
+    pub target: RequestTarget,
+    // decoded and normalized path, used for routing; empty for CONNECT and `*` for server-wide OPTIONS
     pub path: String,
+    // path as sent in the request target, still percent-encoded
+    pub raw_path: String,
     pub version: HttpVersion,
-    pub headers: HashMap<String, String>,
-    pub body: Option<String>,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+    pub trailers: HeaderMap,
     url: Url,
-
+    pub(crate) connection: ConnectionInfo,
 }
 
 impl HttpRequest {
-    pub fn new(s: &str) -> Self {
-        let (first_line, headers, body) = parse_http_request(s);
-        let mut first_line = first_line.split_ascii_whitespace();
-        let method: HttpMethod = first_line.next().map_or(HttpMethod::UNDEFINED, |it| it.into());
-        let path: String = first_line.next().map_or("".to_string(), |it| it.into());
-        let version: HttpVersion = first_line.next().map_or(HttpVersion::UNDEFINED, |it| it.into());
-        let headers = headers.iter().map(|it| parse_header(it))
-            .filter(|it| it.is_ok()).map(|it| it.unwrap()).collect();
-        let body = body.map(|it| it.to_string());
-        let url = Url::parse(&format!("{}{}", "http://undefined", &path)).unwrap();
-        HttpRequest { method, path: url.path().to_string(), version, headers, body, url }
+    pub fn new<T: AsRef<[u8]>>(s: T) -> Result<Self, ParseError> {
+        Self::with_path_policy(s, &PathPolicy::default())
     }
+
+    pub fn with_path_policy<T: AsRef<[u8]>>(s: T, path_policy: &PathPolicy) -> Result<Self, ParseError> {
+        let (first_line, headers, body) = parse_http_request(s.as_ref());
+        let (method, target, version) = parse_request_line(&first_line)?;
+        let headers = headers.iter().map(|it| parse_header(it)).collect::<Result<HeaderMap, ParseError>>()?;
+        let target = parse_target(&method, target)?;
+        let url = effective_uri(&target, headers.get("Host"))?;
+        let raw_path = match &target {
+            RequestTarget::Origin(target) => strip_query(target).to_string(),
+            RequestTarget::Absolute(target) => {
+                let after_scheme = target.split_once("://").map_or(&target[..], |it| it.1);
+                match strip_query(after_scheme).find('/') {
+                    Some(index) => strip_query(after_scheme)[index..].to_string(),
+                    None => String::from("/"),
+                }
+            }
+            RequestTarget::Authority(_) => String::new(),
+            RequestTarget::Asterisk => String::from("*"),
+        };
+        let path = match target {
+            RequestTarget::Origin(_) | RequestTarget::Absolute(_) => normalize_path(&raw_path, path_policy)?,
+            _ => raw_path.clone(),
+        };
+        let mut http_request = HttpRequest {
+            method, target, path, raw_path, version, headers, body: None, trailers: HeaderMap::new(), url,
+            connection: ConnectionInfo::default(),
+        };
+        http_request.body = body.map(|it| http_request.decode_body(it)).transpose()?;
+        Ok(http_request)
+    }
+
+    fn decode_body(&mut self, body: &[u8]) -> Result<Vec<u8>, ParseError> {
+        if !self.joined_header("Transfer-Encoding").is_some_and(|it| is_chunked(&it)) {
+            return Ok(body.to_vec());
+        }
+        // the decoded payload is never longer than its encoding
+        let (body, trailers) = decode_chunked(&mut &body[..], body.len())?;
+        self.trailers.extend(trailers);
+        Ok(body)
+    }
+
+    /// Looks up a header value, ignoring the case of the name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Whether the connection stays open after this request. HTTP/1.1 keeps it open unless
+    /// `Connection: close` is sent, HTTP/1.0 closes it unless `Connection: keep-alive` is sent.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| self.headers.get_all("Connection")
+            .any(|it| it.split(',').any(|it| it.trim().eq_ignore_ascii_case(option)));
+        match self.version {
+            HttpVersion::V1_1 => !has_option("close"),
+            HttpVersion::V1_0 => has_option("keep-alive"),
+            _ => false,
+        }
+    }
+
+    /// Whether the client waits for `100 Continue` before it sends the body. Expectations other
+    /// than `100-continue` are answered with 417, HTTP/1.0 requests have their expectation ignored.
+    pub fn expects_continue(&self) -> Result<bool, HttpError> {
+        if self.version != HttpVersion::V1_1 {
+            return Ok(false);
+        }
+        match self.joined_header("Expect") {
+            Some(expect) if expect.trim().eq_ignore_ascii_case("100-continue") => Ok(true),
+            Some(expect) => Err(HttpError::expectation_failed(format!("unsupported expectation: {}", expect))),
+            None => Ok(false),
+        }
+    }
+
     pub fn path_match(&self, regex: &Regex) -> bool {
         regex.is_match(&self.path)
     }
 
+    /// The connection the request was read from, with the client address.
+    pub fn connection(&self) -> &ConnectionInfo {
+        &self.connection
+    }
+
+    /// The effective request URI, with the scheme, host and port the client addressed.
+    pub fn uri(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.url.host_str()
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.url.port_or_known_default()
+    }
+
     pub fn query(&self) -> Option<&str> {
         self.url.query()
     }
@@ -88,48 +406,163 @@ impl HttpRequest {
         self.url.query_pairs().into_owned().collect()
     }
 
-    pub fn body(&self) -> Option<&str> {
+    /// The cookies of all `Cookie` headers.
+    pub fn cookies(&self) -> MultiMap<String, String> {
+        self.headers.get_all("Cookie").flat_map(parse_cookie_header).collect()
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers.get_all("Cookie").flat_map(parse_cookie_header)
+            .find(|it| it.0 == name).map(|it| it.1)
+    }
+
+    pub fn content_type(&self) -> Option<MediaType> {
+        self.header("Content-Type").and_then(MediaType::parse)
+    }
+
+    /// The elements of an `Accept*` header with their q-values, highest first.
+    pub fn quality_values(&self, name: &str) -> Vec<QualityItem> {
+        self.joined_header(name).map(|it| parse_quality_values(&it)).unwrap_or_default()
+    }
+
+    /// Picks the offered media type the client prefers according to `Accept`,
+    /// answers 406 Not Acceptable when it accepts none of them.
+    pub fn negotiate<'a>(&self, offers: &[&'a str]) -> Result<&'a str, HttpError> {
+        negotiate_media_type(self.joined_header("Accept").as_deref(), offers)
+            .ok_or_else(|| HttpError::not_acceptable(format!("available: {}", offers.join(", "))))
+    }
+
+    pub fn negotiate_language<'a>(&self, offers: &[&'a str]) -> Option<&'a str> {
+        negotiate_language(self.joined_header("Accept-Language").as_deref(), offers)
+    }
+
+    pub fn negotiate_charset<'a>(&self, offers: &[&'a str]) -> Option<&'a str> {
+        negotiate_charset(self.joined_header("Accept-Charset").as_deref(), offers)
+    }
+
+    pub fn negotiate_encoding<'a>(&self, offers: &[&'a str]) -> Option<&'a str> {
+        negotiate_encoding(self.joined_header("Accept-Encoding").as_deref(), offers)
+    }
+
+    // list headers may be split over several fields
+    pub(crate) fn joined_header(&self, name: &str) -> Option<String> {
+        let values: Vec<&str> = self.headers.get_all(name).collect();
+        if values.is_empty() { None } else { Some(values.join(", ")) }
+    }
+
+    /// The fields of an `application/x-www-form-urlencoded` body, decoded with the charset
+    /// of the content type. Empty for any other body.
+    pub fn form(&self) -> MultiMap<String, String> {
+        match (self.content_type(), self.body()) {
+            (Some(content_type), Some(body)) if content_type.mime == "application/x-www-form-urlencoded" => {
+                decode_form(body, content_type.param("charset").unwrap_or("utf-8"))
+            }
+            _ => MultiMap::new(),
+        }
+    }
+
+    /// A parser over the parts of a buffered `multipart/form-data` body. Routes with
+    /// `Route::stream_body` parse the body as it arrives with `HttpChannel::multipart`.
+    pub fn multipart(&self, limits: MultipartLimits) -> Result<Multipart<&[u8]>, ParseError> {
+        Ok(Multipart::new(self.body().unwrap_or(&[]), &self.multipart_boundary()?, limits))
+    }
+
+    pub(crate) fn multipart_boundary(&self) -> Result<String, ParseError> {
+        let content_type = self.content_type().filter(|it| it.mime == "multipart/form-data")
+            .ok_or_else(|| ParseError::InvalidBody(String::from("not a multipart/form-data body")))?;
+        match content_type.param("boundary") {
+            Some(boundary) if !boundary.is_empty() && boundary.len() <= 70 => Ok(boundary.to_string()),
+            _ => Err(ParseError::InvalidBody(String::from("missing or invalid multipart boundary"))),
+        }
+    }
+
+    /// Deserializes a JSON body. Answers 415 when the body is not JSON and 400 when it is invalid.
+    #[cfg(feature = "json")]
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, HttpError> {
+        match self.content_type() {
+            Some(content_type) if content_type.mime == "application/json" || content_type.mime.ends_with("+json") => {
+                serde_json::from_slice(self.body().unwrap_or(&[])).map_err(|e| HttpError::bad_request(e.to_string()))
+            }
+            _ => Err(HttpError::unsupported_media_type(String::from("expected an application/json body"))),
+        }
+    }
+
+    /// Query parameters and form fields together, query values come first.
+    pub fn params(&self) -> MultiMap<String, String> {
+        let mut params = self.query_pair();
+        for (name, values) in self.form() {
+            params.insert_many(name, values);
+        }
+        params
+    }
+
+    pub fn body(&self) -> Option<&[u8]> {
         self.body.as_ref().map(|it| &it[..])
     }
+
+    /// The body as text, `None` when there is no body or it is not valid UTF-8.
+    pub fn body_str(&self) -> Option<&str> {
+        self.body.as_ref().and_then(|it| str::from_utf8(it).ok())
+    }
+
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers.get(name)
+    }
 }
 
-fn parse_http_request(value: &str) -> (String, Vec<String>, Option<&str>) {
-    let mut line = String::new();
+fn decode_form(body: &[u8], charset: &str) -> MultiMap<String, String> {
+    let decode = |s: &[u8]| {
+        let s: Vec<u8> = s.iter().map(|it| if *it == b'+' { b' ' } else { *it }).collect();
+        let bytes: Vec<u8> = percent_decode(&s).collect();
+        match charset.to_ascii_lowercase().as_str() {
+            "iso-8859-1" | "latin1" | "us-ascii" => decode_latin1(&bytes),
+            _ => String::from_utf8_lossy(&bytes).into_owned(),
+        }
+    };
+    body.split(|it| *it == b'&').filter(|it| !it.is_empty()).map(|pair| {
+        match pair.iter().position(|it| *it == b'=') {
+            Some(index) => (decode(&pair[..index]), decode(&pair[index + 1..])),
+            None => (decode(pair), String::new()),
+        }
+    }).collect()
+}
+
+/// Splits a raw request into the request line, the header lines and the body.
+/// Lines are decoded as ISO-8859-1, so obs-text in header values is kept instead of rejected.
+fn parse_http_request(value: &[u8]) -> (String, Vec<String>, Option<&[u8]>) {
     let mut first_line = "".to_string();
     let mut header_list: Vec<String> = Vec::new();
     let mut line_count = 0;
-    let mut body_start_index = 0;
-    for (i, ch) in value.chars().enumerate() {
-        body_start_index = i;
-        if ch == '\n' {
-            if line.ends_with("\r") {
-                line.pop();
-            }
-            line_count += 1;
-            if line_count == 1 {
-                first_line = line.trim_end().to_string();
-            } else if !line.is_empty() {
-                header_list.push(line.clone());
-            } else {
-                break;
-            }
-            line.clear();
+    let mut line_start = 0;
+    let mut body_start_index = value.len();
+    while let Some(i) = value[line_start..].iter().position(|it| *it == b'\n') {
+        let mut line = &value[line_start..line_start + i];
+        line_start += i + 1;
+        if line.ends_with(b"\r") {
+            line = &line[..line.len() - 1];
+        }
+        line_count += 1;
+        if line_count == 1 {
+            first_line = decode_latin1(line).trim_end().to_string();
+        } else if !line.is_empty() {
+            // obs-fold continuation lines are kept, so the header parser rejects them
+            header_list.push(decode_latin1(line));
         } else {
-            line.push(ch);
+            body_start_index = line_start;
+            break;
         }
     }
-    body_start_index = body_start_index + value[body_start_index..].find('\n').map(|it| it + 1).unwrap_or(0);
-    let body_end_index = value.len();
-    let body = if body_start_index < body_end_index { Some(&value[body_start_index..body_end_index]) } else { None };
-    return (first_line, header_list, body);
+    let body = if body_start_index < value.len() { Some(&value[body_start_index..]) } else { None };
+    (first_line, header_list, body)
 }
 
 #[cfg(test)]
 mod test {
-    use std::net::TcpListener;
     use super::*;
+    use crate::status::StatusCode;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test1() {
         let s = "GET /a/b/c HTTP/1.1
 Host: 127.0.0.1:7878
@@ -141,7 +574,7 @@ Connection: keep-alive
 Upgrade-Insecure-Requests: 1
 
 ";
-        let http_request: HttpRequest = HttpRequest::new(s);
+        let http_request: HttpRequest = HttpRequest::new(s).unwrap();
         println!("{:?}", http_request);
         assert!(http_request.path_match(&Regex::new(r"^/.*").unwrap()));
         assert!(http_request.path_match(&Regex::new(r"^/a/.*").unwrap()));
@@ -155,7 +588,7 @@ Upgrade-Insecure-Requests: 1
         let s = "GET /a/b/c HTTP/1.1
 
 ";
-        let http_request: HttpRequest = HttpRequest::new(s);
+        let http_request: HttpRequest = HttpRequest::new(s).unwrap();
         println!("{:?}", http_request);
     }
 
@@ -174,7 +607,7 @@ Upgrade-Insecure-Requests: 1
 hello body
 123
 321";
-        let result = parse_http_request(s);
+        let result = parse_http_request(s.as_bytes());
 
         let (first, headers, body) = dbg!(result);
 
@@ -183,7 +616,7 @@ hello body
         println!("{:?}", headers);
         assert!(headers.contains(&"Host: 127.0.0.1:7878".to_string()));
 
-        assert_eq!(body.unwrap(), "hello body
+        assert_eq!(body.unwrap(), b"hello body
 123
 321");
     }
@@ -193,11 +626,237 @@ hello body
         let s = "GET /get?show_env=1&id=abc&id=efg HTTP/1.1
 
     ";
-        let http_request: HttpRequest = HttpRequest::new(s);
+        let http_request: HttpRequest = HttpRequest::new(s).unwrap();
         println!("{:?}", http_request);
         println!("{:?}", http_request.query());
         assert_eq!(http_request.query(), Some("show_env=1&id=abc&id=efg"));
         println!("{:?}", http_request.query_pair());
         assert_eq!(http_request.query_pair().get_vec("id"), Some(&vec![String::from("abc"), String::from("efg")]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chunked_body() {
+        let s = "POST /upload HTTP/1.1\r
+Transfer-Encoding: chunked\r
+\r
+5\r
+hello\r
+6;ext=1\r
+ world\r
+0\r
+X-Checksum: 42\r
+\r
+";
+        let http_request: HttpRequest = HttpRequest::new(s).unwrap();
+        assert_eq!(http_request.body_str(), Some("hello world"));
+        assert_eq!(http_request.trailer("x-checksum"), Some("42"));
+    }
+
+    #[test]
+    fn test_binary_body() {
+        let mut s = b"POST /upload HTTP/1.1\r\nX-Name: caf\xe9\r\n\r\n".to_vec();
+        s.extend_from_slice(&[0x89, 0x50, 0x4e, 0x47, 0x00, 0xff]);
+        let http_request: HttpRequest = HttpRequest::new(&s).unwrap();
+        assert_eq!(http_request.header("X-Name"), Some("café"));
+        assert_eq!(http_request.body(), Some(&[0x89, 0x50, 0x4e, 0x47, 0x00, 0xff][..]));
+        assert_eq!(http_request.body_str(), None);
+    }
+
+    #[test]
+    fn test_method() {
+        assert_eq!(HttpRequest::new("DELETE /a HTTP/1.1\n\n").unwrap().method, HttpMethod::DELETE);
+        assert_eq!(HttpRequest::new("PATCH /a HTTP/1.1\n\n").unwrap().method, HttpMethod::PATCH);
+        let http_request = HttpRequest::new("PROPFIND /a HTTP/1.1\n\n").unwrap();
+        assert_eq!(http_request.method, HttpMethod::Extension(String::from("PROPFIND")));
+        assert_eq!(http_request.method.to_string(), "PROPFIND");
+        assert!(HttpRequest::new("G(ET /a HTTP/1.1\n\n").is_err());
+    }
+
+    #[test]
+    fn test_version() {
+        assert_eq!(HttpVersion::from("HTTP/1.0"), HttpVersion::V1_0);
+        assert_eq!(HttpVersion::from("HTTP/1.1"), HttpVersion::V1_1);
+        assert_eq!(HttpVersion::from("HTTP/1.2"), HttpVersion::V1_1);
+        assert_eq!(HttpVersion::from("HTTP/2.0"), HttpVersion::Unsupported(2, 0));
+        assert_eq!(HttpVersion::from("HTTP/1.10"), HttpVersion::UNDEFINED);
+        assert_eq!(HttpVersion::from("http/1.1"), HttpVersion::UNDEFINED);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        assert!(HttpRequest::new("GET / HTTP/1.1\n\n").unwrap().keep_alive());
+        assert!(!HttpRequest::new("GET / HTTP/1.1\nConnection: Close\n\n").unwrap().keep_alive());
+        assert!(!HttpRequest::new("GET / HTTP/1.0\n\n").unwrap().keep_alive());
+        assert!(HttpRequest::new("GET / HTTP/1.0\nConnection: Keep-Alive\n\n").unwrap().keep_alive());
+    }
+
+    #[test]
+    fn test_repeated_headers() {
+        let http_request = HttpRequest::new("GET / HTTP/1.1\nAccept: text/html\naccept: application/json\n\n").unwrap();
+        assert_eq!(http_request.headers.get_all("ACCEPT").collect::<Vec<_>>(), vec!["text/html", "application/json"]);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let parse = |s: &str| HttpRequest::new(s).unwrap_err();
+        assert!(matches!(parse("GET /a\n\n"), ParseError::BadRequestLine(_)));
+        assert!(matches!(parse("GET /a HTTP/1.1 extra\n\n"), ParseError::BadRequestLine(_)));
+        assert!(matches!(parse("GET /a XTTP/1.1\n\n"), ParseError::BadRequestLine(_)));
+        assert!(matches!(parse("GET /a HTTP/2.0\n\n"), ParseError::UnsupportedVersion(_)));
+        assert!(matches!(parse("GET /a HTTP/1.1\nHost 127.0.0.1\n\n"), ParseError::BadHeader(_)));
+        assert!(matches!(parse("GET /a HTTP/1.1\nHost : 127.0.0.1\n\n"), ParseError::BadHeader(_)));
+        assert!(matches!(parse("GET /a HTTP/1.1\nX-A: 1\n folded\n\n"), ParseError::BadHeader(_)));
+        assert!(matches!(parse("GET a/b HTTP/1.1\n\n"), ParseError::InvalidUri(_)));
+        assert!(matches!(parse("POST /a HTTP/1.1\nTransfer-Encoding: chunked\n\nz\r\n"), ParseError::InvalidBody(_)));
+        assert!(matches!(parse("POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\nab\r\n0\r\n\r\n"),
+                         ParseError::PayloadTooLarge));
+    }
+
+    #[test]
+    fn test_form() {
+        let s = "POST /post?id=1 HTTP/1.1
+Content-Type: application/x-www-form-urlencoded
+
+id=999&value=a+b%26c&value=%E4%BD%A0&empty";
+        let http_request = HttpRequest::new(s).unwrap();
+        let form = http_request.form();
+        assert_eq!(form.get("id"), Some(&String::from("999")));
+        assert_eq!(form.get_vec("value"), Some(&vec![String::from("a b&c"), String::from("你")]));
+        assert_eq!(form.get("empty"), Some(&String::new()));
+        assert_eq!(http_request.params().get_vec("id"), Some(&vec![String::from("1"), String::from("999")]));
+
+        let s = "POST /post HTTP/1.1
+Content-Type: application/x-www-form-urlencoded; charset=ISO-8859-1
+
+name=caf%E9";
+        assert_eq!(HttpRequest::new(s).unwrap().form().get("name"), Some(&String::from("café")));
+
+        let s = "POST /post HTTP/1.1
+Content-Type: application/json
+
+{}";
+        assert!(HttpRequest::new(s).unwrap().form().is_empty());
+    }
+
+    #[test]
+    fn test_multipart() {
+        let s = "POST /post HTTP/1.1\r
+Content-Type: multipart/form-data; boundary=\"WebAppBoundary\"\r
+\r
+--WebAppBoundary\r
+Content-Disposition: form-data; name=\"element-name\"\r
+\r
+Name\r
+--WebAppBoundary--\r
+";
+        let http_request = HttpRequest::new(s).unwrap();
+        let mut multipart = http_request.multipart(MultipartLimits::default()).unwrap();
+        assert_eq!(multipart.next_part().unwrap().unwrap().text(), Some("Name"));
+        assert!(multipart.next_part().unwrap().is_none());
+
+        let http_request = HttpRequest::new("POST /post HTTP/1.1\nContent-Type: multipart/form-data\n\n").unwrap();
+        assert!(http_request.multipart(MultipartLimits::default()).is_err());
+    }
+
+    #[test]
+    fn test_cookies() {
+        let http_request = HttpRequest::new("GET / HTTP/1.1\nCookie: session=abc; theme=dark\nCookie: lang=en\n\n").unwrap();
+        assert_eq!(http_request.cookie("session"), Some(String::from("abc")));
+        assert_eq!(http_request.cookie("lang"), Some(String::from("en")));
+        assert_eq!(http_request.cookie("missing"), None);
+        assert_eq!(http_request.cookies().len(), 3);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let http_request = HttpRequest::new("POST /post HTTP/1.1\nContent-Type: application/json\n\n{\"id\": 999}").unwrap();
+        let value: serde_json::Value = http_request.json().unwrap();
+        assert_eq!(value["id"], 999);
+
+        let http_request = HttpRequest::new("POST /post HTTP/1.1\nContent-Type: application/json\n\n{\"id\": ").unwrap();
+        assert_eq!(http_request.json::<serde_json::Value>().unwrap_err().status, StatusCode::BAD_REQUEST);
+
+        let http_request = HttpRequest::new("POST /post HTTP/1.1\nContent-Type: text/plain\n\n{}").unwrap();
+        assert_eq!(http_request.json::<serde_json::Value>().unwrap_err().status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn test_request_target() {
+        let http_request = HttpRequest::new("GET /a/b?x=1 HTTP/1.1\nHost: example.com:8080\n\n").unwrap();
+        assert_eq!(http_request.target, RequestTarget::Origin(String::from("/a/b?x=1")));
+        assert_eq!(http_request.uri().as_str(), "http://example.com:8080/a/b?x=1");
+        assert_eq!(http_request.host(), Some("example.com"));
+        assert_eq!(http_request.port(), Some(8080));
+
+        let http_request = HttpRequest::new("GET https://example.com/a?x=1 HTTP/1.1\nHost: other\n\n").unwrap();
+        assert!(matches!(http_request.target, RequestTarget::Absolute(_)));
+        assert_eq!(http_request.uri().as_str(), "https://example.com/a?x=1");
+        assert_eq!(http_request.path, "/a");
+        assert_eq!(http_request.query(), Some("x=1"));
+        assert_eq!(http_request.port(), Some(443));
+
+        let http_request = HttpRequest::new("CONNECT example.com:443 HTTP/1.1\nHost: example.com:443\n\n").unwrap();
+        assert_eq!(http_request.target, RequestTarget::Authority(String::from("example.com:443")));
+        assert_eq!(http_request.host(), Some("example.com"));
+        assert_eq!(http_request.path, "");
+
+        let http_request = HttpRequest::new("OPTIONS * HTTP/1.1\nHost: example.com\n\n").unwrap();
+        assert_eq!(http_request.target, RequestTarget::Asterisk);
+        assert_eq!(http_request.path, "*");
+
+        let http_request = HttpRequest::new("GET /a HTTP/1.0\n\n").unwrap();
+        assert_eq!(http_request.uri().as_str(), "http://localhost/a");
+
+        assert!(matches!(HttpRequest::new("GET * HTTP/1.1\n\n"), Err(ParseError::InvalidUri(_))));
+        assert!(matches!(HttpRequest::new("CONNECT /a HTTP/1.1\n\n"), Err(ParseError::InvalidUri(_))));
+        assert!(matches!(HttpRequest::new("CONNECT example.com:+443 HTTP/1.1\n\n"), Err(ParseError::InvalidUri(_))));
+        assert!(matches!(HttpRequest::new("CONNECT example.com: HTTP/1.1\n\n"), Err(ParseError::InvalidUri(_))));
+        assert!(matches!(HttpRequest::new("GET ftp://example.com/ HTTP/1.1\n\n"), Err(ParseError::InvalidUri(_))));
+        assert!(matches!(HttpRequest::new("GET / HTTP/1.1\nHost: a/b\n\n"), Err(ParseError::BadHeader(_))));
+    }
+
+    #[test]
+    fn test_check_host() {
+        assert!(check_host(&HttpRequest::new("GET / HTTP/1.1\nHost: a\n\n").unwrap()).is_ok());
+        assert!(check_host(&HttpRequest::new("GET / HTTP/1.0\n\n").unwrap()).is_ok());
+        assert!(matches!(check_host(&HttpRequest::new("GET / HTTP/1.1\n\n").unwrap()), Err(ParseError::MissingHost)));
+        assert!(matches!(check_host(&HttpRequest::new("GET / HTTP/1.1\nHost: a\nHost: b\n\n").unwrap()), Err(ParseError::MissingHost)));
+    }
+
+    #[test]
+    fn test_normalized_path() {
+        let http_request = HttpRequest::new("GET /a//b/./%63/../d%20e/?x=%2F HTTP/1.0\n\n").unwrap();
+        assert_eq!(http_request.raw_path, "/a//b/./%63/../d%20e/");
+        assert_eq!(http_request.path, "/a/b/d e/");
+        assert_eq!(http_request.query(), Some("x=%2F"));
+        assert!(http_request.path_match(&Regex::new(r"^/a/b/d e/$").unwrap()));
+
+        let http_request = HttpRequest::new("GET http://example.com/a/%2e%2e/b HTTP/1.1\nHost: example.com\n\n").unwrap();
+        assert_eq!(http_request.raw_path, "/a/%2e%2e/b");
+        assert_eq!(http_request.path, "/b");
+        assert_eq!(HttpRequest::new("GET http://example.com?a=/b HTTP/1.0\n\n").unwrap().path, "/");
+
+        assert!(matches!(HttpRequest::new("GET /a%2Fb HTTP/1.0\n\n"), Err(ParseError::InvalidUri(_))));
+        assert!(matches!(HttpRequest::new("GET /a%00 HTTP/1.0\n\n"), Err(ParseError::InvalidUri(_))));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let http_request = HttpRequest::new("GET / HTTP/1.0\nAccept: text/html;q=0.5\nAccept: application/json\nAccept-Language: de, en;q=0.8\n\n").unwrap();
+        assert_eq!(http_request.negotiate(&["text/html", "application/json"]).unwrap(), "application/json");
+        assert_eq!(http_request.negotiate(&["text/plain"]).unwrap_err().status, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(http_request.quality_values("Accept-Language")[1].value, "en");
+        assert_eq!(http_request.negotiate_language(&["en", "de-AT"]), Some("de-AT"));
+        assert_eq!(http_request.negotiate_charset(&["utf-8"]), Some("utf-8"));
+    }
+
+    #[test]
+    fn test_expects_continue() {
+        let expects = |s: &str| HttpRequest::new(s).unwrap().expects_continue().map_err(|it| it.status.as_u16());
+        assert_eq!(expects("POST / HTTP/1.1\nHost: a\nExpect: 100-Continue\n\n"), Ok(true));
+        assert_eq!(expects("POST / HTTP/1.1\nHost: a\n\n"), Ok(false));
+        assert_eq!(expects("POST / HTTP/1.1\nHost: a\nExpect: 200-ok\n\n"), Err(417));
+        assert_eq!(expects("POST / HTTP/1.0\nExpect: 100-continue\n\n"), Ok(false));
+    }
+}
@@ -1,49 +1,190 @@
-use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
 use std::str;
 
-use crate::request::HttpVersion;
+#[cfg(any(feature = "json", feature = "templates"))]
+use serde::Serialize;
+use crate::body::Body;
+use crate::chunked::ChunkedWriter;
+use crate::cookie::Cookie;
+use crate::date::http_date_now;
+use crate::error::HttpError;
+use crate::header::HeaderMap;
+use crate::request::{HttpMethod, HttpRequest, HttpVersion, ParseError};
+use crate::status::StatusCode;
+
+/// The status of a redirect: whether it is permanent, and whether the method may change to GET.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectKind {
+    // 301, clients may change POST to GET
+    MovedPermanently,
+    // 302, clients may change POST to GET
+    Found,
+    // 303, the target is fetched with GET
+    SeeOther,
+    // 307, the method and body are kept
+    Temporary,
+    // 308, the method and body are kept
+    Permanent,
+}
+
+impl RedirectKind {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RedirectKind::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+            RedirectKind::Found => StatusCode::FOUND,
+            RedirectKind::SeeOther => StatusCode::SEE_OTHER,
+            RedirectKind::Temporary => StatusCode::TEMPORARY_REDIRECT,
+            RedirectKind::Permanent => StatusCode::PERMANENT_REDIRECT,
+        }
+    }
+}
+
+/// The `Server` header sent unless the router configures another, see `HttpRouter::server_name`.
+pub const DEFAULT_SERVER_NAME: &str = "Rust Server/1.0";
 
 #[derive(Debug)]
 pub struct HttpResponse {
-    pub code: i32,
-    pub message: String,
+    pub status: StatusCode,
     pub version: HttpVersion,
-    pub headers: HashMap<String, String>,
-    body: Option<Vec<u8>>,
+    pub headers: HeaderMap,
+    // sent after a chunked body, for HTTP/1.0 clients they are dropped
+    pub trailers: HeaderMap,
+    body: Option<Body>,
+    // the response to a HEAD request, its body is left out when it is written
+    head_request: bool,
+    // set once the head is written, after that no other response can be sent
+    head_sent: bool,
+}
+
+impl Default for HttpResponse {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HttpResponse {
     pub fn new() -> Self {
-        let mut headers = HashMap::new();
-        headers.insert(String::from("Server"), String::from("Rust Server/1.0"));
-        HttpResponse { code: 200, message: String::from("OK"), version: HttpVersion::V1, headers, body: None }
+        HttpResponse { status: StatusCode::OK, version: HttpVersion::V1_1, headers: HeaderMap::new(), trailers: HeaderMap::new(), body: None, head_request: false, head_sent: false }
     }
 
-    pub fn success(&mut self) -> &mut Self {
-        self.code = 200;
-        self.message = String::from("OK");
+    /// Creates a response for the request: HTTP/1.0 clients get an HTTP/1.0 response, and the
+    /// `Connection` header tells the client whether the connection stays open. The server
+    /// name is sent as the `Server` header, `None` leaves it out.
+    pub fn for_request(http_request: &HttpRequest, server_name: Option<&str>) -> Self {
+        let mut response = Self::new();
+        if let Some(server_name) = server_name {
+            response.header(String::from("Server"), server_name.to_string());
+        }
+        response.head_request = http_request.method == HttpMethod::HEAD;
+        let keep_alive = http_request.keep_alive();
+        if http_request.version == HttpVersion::V1_0 {
+            response.version = HttpVersion::V1_0;
+            if keep_alive {
+                response.header(String::from("Connection"), String::from("keep-alive"));
+            }
+        }
+        if !keep_alive {
+            response.header(String::from("Connection"), String::from("close"));
+        }
+        response
+    }
+
+    pub fn status(&mut self, status: StatusCode) -> &mut Self {
+        self.status = status;
         self
     }
 
+    pub fn success(&mut self) -> &mut Self {
+        self.status(StatusCode::OK)
+    }
+
+    pub fn created(&mut self) -> &mut Self {
+        self.status(StatusCode::CREATED)
+    }
+
+    /// 204 No Content, any body is dropped.
+    pub fn no_content(&mut self) -> &mut Self {
+        self.body = None;
+        self.headers.remove("Content-Length");
+        self.status(StatusCode::NO_CONTENT)
+    }
+
+    pub fn redirect(&mut self, location: String, kind: RedirectKind) -> &mut Self {
+        self.status_page(kind.status()).header(String::from("Location"), location)
+    }
+
+    /// 401 Unauthorized, the challenge tells the client how to authenticate, e.g. `Basic realm="app"`.
+    pub fn unauthorized(&mut self, challenge: String) -> &mut Self {
+        self.status_page(StatusCode::UNAUTHORIZED).header(String::from("WWW-Authenticate"), challenge)
+    }
+
+    pub fn forbidden(&mut self) -> &mut Self {
+        self.status_page(StatusCode::FORBIDDEN)
+    }
+
+    pub fn conflict(&mut self) -> &mut Self {
+        self.status_page(StatusCode::CONFLICT)
+    }
+
     pub fn bad_request(&mut self) -> &mut Self {
-        self.code = 400;
-        self.message = String::from("Bad Request");
-        self.body_str(String::from("400 Bad Request"));
-        self
+        self.status_page(StatusCode::BAD_REQUEST)
+    }
+
+    pub fn payload_too_large(&mut self) -> &mut Self {
+        self.status_page(StatusCode::PAYLOAD_TOO_LARGE)
+    }
+
+    pub fn uri_too_long(&mut self) -> &mut Self {
+        self.status_page(StatusCode::URI_TOO_LONG)
+    }
+
+    pub fn header_fields_too_large(&mut self) -> &mut Self {
+        self.status_page(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
     }
 
     pub fn not_found(&mut self) -> &mut Self {
-        self.code = 404;
-        self.message = String::from("Not Found");
-        self.body_str(String::from("404 Not Found"));
-        self
+        self.status_page(StatusCode::NOT_FOUND)
     }
 
     pub fn error(&mut self) -> &mut Self {
-        self.code = 500;
-        self.message = String::from("Server Error");
-        self.body_str(String::from("500 Server Error"));
-        self
+        self.status_page(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    pub fn version_not_supported(&mut self) -> &mut Self {
+        self.status_page(StatusCode::HTTP_VERSION_NOT_SUPPORTED)
+    }
+
+    // sets the status with the status line as the body, e.g. `404 Not Found`
+    fn status_page(&mut self, status: StatusCode) -> &mut Self {
+        self.status(status).body_str(status.to_string())
+    }
+
+    /// Answers an error returned by a handler, with the status of an `HttpError` or else 500.
+    /// A `ParseError`, e.g. from a body read by the handler, is answered like a request that
+    /// could not be read.
+    pub fn handler_error(&mut self, error: &(dyn Error + 'static)) -> &mut Self {
+        if let Some(e) = error.downcast_ref::<ParseError>() {
+            return self.parse_error(e);
+        }
+        match error.downcast_ref::<HttpError>() {
+            Some(e) => self.status(e.status).body_str(e.detail.clone()),
+            None => self.error().body_str(error.to_string()),
+        }
+    }
+
+    /// Answers a request that could not be parsed. The connection is closed afterwards,
+    /// since the rest of the stream can't be trusted.
+    pub fn parse_error(&mut self, error: &ParseError) -> &mut Self {
+        match error {
+            ParseError::PayloadTooLarge => self.payload_too_large(),
+            ParseError::UriTooLong => self.uri_too_long(),
+            ParseError::HeaderTooLarge => self.header_fields_too_large(),
+            ParseError::UnsupportedVersion(_) => self.version_not_supported(),
+            _ => self.bad_request(),
+        };
+        self.header(String::from("Connection"), String::from("close"))
     }
 }
 
@@ -53,49 +194,223 @@ impl HttpResponse {
         self
     }
 
-    pub fn headers(&mut self, headers: HashMap<String, String>) -> &mut Self {
-        self.headers.extend(headers);
+    /// Adds a value without replacing existing ones, e.g. for `Set-Cookie`.
+    pub fn append_header(&mut self, name: String, value: String) -> &mut Self {
+        self.headers.append(name, value);
         self
     }
 
-    pub fn body_str(&mut self, body: String) -> &mut Self {
-        self.header(String::from("Content-Length"), body.len().to_string());
-        self.body = Some(body.into());
+    /// Adds a `Set-Cookie` header, a response can set several cookies.
+    pub fn set_cookie(&mut self, cookie: &Cookie) -> &mut Self {
+        self.append_header(String::from("Set-Cookie"), cookie.to_string())
+    }
+
+    pub fn headers(&mut self, headers: HeaderMap) -> &mut Self {
+        for (name, value) in headers {
+            self.headers.insert(name, value);
+        }
         self
     }
 
+    pub fn body_str(&mut self, body: String) -> &mut Self {
+        self.body(body.into())
+    }
+
     pub fn body(&mut self, body: Vec<u8>) -> &mut Self {
-        self.header(String::from("Content-Length"), body.len().to_string());
+        self.body_stream(Body::Bytes(body))
+    }
+
+    /// Sets a body that may be produced while it is sent. A body without a known length
+    /// is sent chunked, or for HTTP/1.0 clients until the connection is closed.
+    pub fn body_stream(&mut self, body: Body) -> &mut Self {
+        match body.len() {
+            Some(length) => self.headers.insert(String::from("Content-Length"), length.to_string()),
+            None => { self.headers.remove("Content-Length"); }
+        }
         self.body = Some(body);
         self
     }
 
+    /// Serializes the value as the body and sets the JSON content type.
+    #[cfg(feature = "json")]
+    pub fn json<T: Serialize>(&mut self, value: &T) -> Result<&mut Self, serde_json::Error> {
+        let body = serde_json::to_vec(value)?;
+        Ok(self.header(String::from("Content-Type"), String::from("application/json")).body(body))
+    }
+
+    /// Renders the template with the context as an HTML body, see `templates::set_templates`.
+    #[cfg(feature = "templates")]
+    pub fn render<S: Serialize>(&mut self, name: &str, context: S) -> Result<&mut Self, minijinja::Error> {
+        let body = crate::templates::render(name, context)?;
+        Ok(self.header(String::from("Content-Type"), String::from("text/html; charset=utf-8")).body_str(body))
+    }
+
+    /// The length of the body, `None` when there is none or it is streamed without a known length.
+    pub fn body_len(&self) -> Option<u64> {
+        self.body.as_ref().and_then(|it| it.len())
+    }
+
+    #[cfg(feature = "compression")]
+    pub(crate) fn has_body(&self) -> bool {
+        self.body.is_some()
+    }
+
+    #[cfg(feature = "compression")]
+    pub(crate) fn take_body(&mut self) -> Option<Body> {
+        self.headers.remove("Content-Length");
+        self.body.take()
+    }
+
+    /// The body when it is held in memory, `None` for streamed bodies.
     pub fn body_ref(&self) -> Option<&[u8]> {
-        self.body.as_ref().map(|it| &it[..])
+        match &self.body {
+            Some(Body::Bytes(bytes)) => Some(&bytes[..]),
+            _ => None,
+        }
     }
 
     pub fn body_str_ref(&self) -> Option<&str> {
-        self.body.as_ref().and_then(|it| str::from_utf8(it).ok())
+        self.body_ref().and_then(|it| str::from_utf8(it).ok())
+    }
+
+    /// Whether the body is sent, a response to HEAD and a 1xx, 204 or 304 response have none.
+    pub fn sends_body(&self) -> bool {
+        !self.head_request && !self.status.is_informational()
+            && self.status != StatusCode::NO_CONTENT && self.status != StatusCode::NOT_MODIFIED
+    }
+
+    /// Whether `write_to` has written the head, a body that failed after it can't be
+    /// replaced by an error response.
+    pub fn head_sent(&self) -> bool {
+        self.head_sent
+    }
+
+    /// Whether the connection may be reused after this response is sent.
+    pub fn keep_alive(&self) -> bool {
+        // after 101 Switching Protocols the connection belongs to the new protocol
+        self.status != StatusCode::SWITCHING_PROTOCOLS && !self.headers.get_all("Connection").any(|it| it.eq_ignore_ascii_case("close"))
     }
 }
 
+/// Writes an interim 1xx response, e.g. `100 Continue`. It has no body and is followed by
+/// the final response to the same request.
+pub fn write_interim<W: Write>(writer: &mut W, status: StatusCode) -> io::Result<()> {
+    write!(writer, "{} {}\r\n\r\n", HttpVersion::V1_1, status)?;
+    writer.flush()
+}
+
+impl HttpResponse {
+    /// Writes the status line, the headers and the body, with CRLF line breaks
+    /// and the body bytes as they are. A streamed body is consumed. When writing fails
+    /// the response gets `Connection: close`, the client can't tell where it ends.
+    pub fn write_to<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let result = self.write_message(writer);
+        if result.is_err() {
+            self.header(String::from("Connection"), String::from("close"));
+        }
+        result
+    }
+
+    fn write_message<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if !self.sends_body() {
+            // HEAD and 304 keep the Content-Length of the body they leave out, 1xx and 204 have none
+            if self.status.is_informational() || self.status == StatusCode::NO_CONTENT {
+                self.headers.remove("Content-Length");
+            }
+            return self.send_head(writer);
+        }
+        match self.body.take() {
+            None => self.send_head(writer),
+            Some(Body::Bytes(bytes)) => {
+                self.send_head(writer)?;
+                writer.write_all(&bytes)?;
+                self.body = Some(Body::Bytes(bytes));
+                Ok(())
+            }
+            Some(Body::Reader(mut reader, Some(length))) => {
+                self.send_head(writer)?;
+                if io::copy(&mut reader.by_ref().take(length), writer)? < length {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "body shorter than its Content-Length"));
+                }
+                Ok(())
+            }
+            Some(body) if self.version == HttpVersion::V1_1 => {
+                self.headers.remove("Content-Length");
+                self.header(String::from("Transfer-Encoding"), String::from("chunked"));
+                if !self.trailers.is_empty() {
+                    let names: Vec<&str> = self.trailers.iter().map(|it| it.0).collect();
+                    self.headers.insert(String::from("Trailer"), names.join(", "));
+                }
+                self.send_head(writer)?;
+                let mut chunked = ChunkedWriter::new(&mut *writer);
+                write_body(body, &mut chunked)?;
+                chunked.finish(&self.trailers.iter().collect::<Vec<_>>())?;
+                Ok(())
+            }
+            Some(body) => {
+                // HTTP/1.0 has no chunked coding, the body ends with the connection
+                self.headers.remove("Content-Length");
+                self.header(String::from("Connection"), String::from("close"));
+                self.send_head(writer)?;
+                write_body(body, writer)
+            }
+        }
+    }
+
+    fn send_head<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.write_head(writer)?;
+        self.head_sent = true;
+        Ok(())
+    }
+
+    /// Writes the status line and the headers up to the blank line, for a body sent separately.
+    /// A `Date` header with the current time is added unless the response has one.
+    pub fn write_head<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut head = format!("{} {}\r\n", self.version, self.status);
+        if !self.headers.contains_key("Date") {
+            head.push_str(&format!("Date: {}\r\n", http_date_now()));
+        }
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())
+    }
+}
+
+fn write_body<W: Write>(body: Body, writer: &mut W) -> io::Result<()> {
+    match body {
+        Body::Bytes(bytes) => writer.write_all(&bytes),
+        Body::Reader(mut reader, _) => io::copy(&mut reader, writer).map(|_| ()),
+        Body::Chunks(chunks) => {
+            for chunk in chunks {
+                writer.write_all(&chunk?)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The serialized response, a body that isn't UTF-8 is shown lossily and a streamed body is left out.
 impl From<&HttpResponse> for String {
     fn from(response: &HttpResponse) -> Self {
-        let first_line = format!("{} {} {}", response.version.to_string(), response.code, response.message);
-        let headers = response.headers.iter().map(|it| format!("{}: {}", it.0, it.1))
-            .reduce(|acc, it| format!("{}\n{}", acc, it)).unwrap_or_else(|| "".to_string());
-        format!("{}\n{}\n\n{}", first_line, headers, response.body_str_ref().unwrap_or_else(|| ""))
+        let mut output = Vec::new();
+        response.write_head(&mut output).expect("writing to a Vec can't fail");
+        output.extend_from_slice(response.body_ref().unwrap_or(&[]));
+        String::from_utf8_lossy(&output).into_owned()
     }
 }
 
-impl ToString for HttpResponse {
-    fn to_string(&self) -> String {
-        self.into()
+impl fmt::Display for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from(self))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::cookie::SameSite;
+    use crate::date::parse_http_date;
     use super::*;
 
     #[test]
@@ -105,7 +420,7 @@ mod test {
         r1.body_str(String::from("hello world"));
         dbg!(r1.to_string());
         assert_eq!("HTTP/1.1 200 OK", r1.to_string().lines().next().unwrap());
-        assert_eq!(Some(&"11".to_string()), r1.headers.get("Content-Length"));
+        assert_eq!(Some("11"), r1.headers.get("Content-Length"));
         assert_eq!(Some("hello world"), r1.body_str_ref());
     }
 
@@ -118,4 +433,213 @@ mod test {
         assert_eq!(None, r1.headers.get("Content-Length"));
         assert_eq!(None, r1.body_ref());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_for_request() {
+        let r1 = HttpResponse::for_request(&HttpRequest::new("GET / HTTP/1.0\n\n").unwrap(), None);
+        assert_eq!("HTTP/1.0 200 OK", r1.to_string().lines().next().unwrap());
+        assert_eq!(Some("close"), r1.headers.get("Connection"));
+        assert!(!r1.keep_alive());
+
+        let r2 = HttpResponse::for_request(&HttpRequest::new("GET / HTTP/1.0\nConnection: keep-alive\n\n").unwrap(), None);
+        assert_eq!(Some("keep-alive"), r2.headers.get("Connection"));
+        assert!(r2.keep_alive());
+
+        let r3 = HttpResponse::for_request(&HttpRequest::new("GET / HTTP/1.1\n\n").unwrap(), None);
+        assert_eq!("HTTP/1.1 200 OK", r3.to_string().lines().next().unwrap());
+        assert_eq!(None, r3.headers.get("Connection"));
+        assert_eq!(None, r3.headers.get("Server"));
+        assert!(r3.keep_alive());
+
+        let r4 = HttpResponse::for_request(&HttpRequest::new("GET / HTTP/1.1\n\n").unwrap(), Some(DEFAULT_SERVER_NAME));
+        assert_eq!(Some("Rust Server/1.0"), r4.headers.get("Server"));
+    }
+
+    #[test]
+    fn test_header_order() {
+        let mut r1 = HttpResponse::for_request(&HttpRequest::new("GET / HTTP/1.1\n\n").unwrap(), Some(DEFAULT_SERVER_NAME));
+        r1.header(String::from("Content-Type"), String::from("text/plain"))
+            .append_header(String::from("Set-Cookie"), String::from("a=1"))
+            .append_header(String::from("Set-Cookie"), String::from("b=2"))
+            .header(String::from("content-type"), String::from("text/html"));
+        let s = r1.to_string();
+        let headers: Vec<&str> = s.lines().skip(1).filter(|it| !it.starts_with("Date: ")).take(4).collect();
+        assert_eq!(vec!["Server: Rust Server/1.0", "Content-Type: text/html", "Set-Cookie: a=1", "Set-Cookie: b=2"], headers);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let mut r1 = HttpResponse::new();
+        r1.parse_error(&HttpRequest::new("GET /a HTTP/3.0\n\n").unwrap_err());
+        assert_eq!("HTTP/1.1 505 HTTP Version Not Supported", r1.to_string().lines().next().unwrap());
+        assert!(!r1.keep_alive());
+
+        let mut r2 = HttpResponse::new();
+        r2.parse_error(&HttpRequest::new("GET /a HTTP/1.1\nbad header\n\n").unwrap_err());
+        assert_eq!("HTTP/1.1 400 Bad Request", r2.to_string().lines().next().unwrap());
+    }
+
+    #[test]
+    fn test_set_cookie() {
+        let mut r1 = HttpResponse::new();
+        r1.set_cookie(Cookie::new(String::from("a"), String::from("1")).unwrap().http_only(true))
+            .set_cookie(Cookie::new(String::from("b"), String::from("2")).unwrap().same_site(SameSite::Strict));
+        assert_eq!(r1.headers.get_all("Set-Cookie").collect::<Vec<_>>(), vec!["a=1; HttpOnly", "b=2; SameSite=Strict"]);
+    }
+
+    #[test]
+    fn test_handler_error() {
+        let mut r1 = HttpResponse::new();
+        r1.handler_error(&HttpError::unsupported_media_type(String::from("expected json")));
+        assert_eq!("HTTP/1.1 415 Unsupported Media Type", r1.to_string().lines().next().unwrap());
+        assert_eq!(Some("expected json"), r1.body_str_ref());
+
+        let mut r2 = HttpResponse::new();
+        let e: Box<dyn Error> = "boom".into();
+        r2.handler_error(e.as_ref());
+        assert_eq!("HTTP/1.1 500 Internal Server Error", r2.to_string().lines().next().unwrap());
+        assert_eq!(Some("boom"), r2.body_str_ref());
+
+        let mut r3 = HttpResponse::new();
+        r3.handler_error(&ParseError::PayloadTooLarge);
+        assert_eq!("HTTP/1.1 413 Payload Too Large", r3.to_string().lines().next().unwrap());
+        assert!(!r3.keep_alive());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let mut r1 = HttpResponse::new();
+        r1.json(&serde_json::json!({"name": "my-name"})).unwrap();
+        assert_eq!(Some("application/json"), r1.headers.get("Content-Type"));
+        assert_eq!(Some("{\"name\":\"my-name\"}"), r1.body_str_ref());
+    }
+
+    #[test]
+    fn test_write_interim() {
+        let mut output = Vec::new();
+        write_interim(&mut output, StatusCode::CONTINUE).unwrap();
+        assert_eq!(output, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn test_response_splitting() {
+        let mut r1 = HttpResponse::new();
+        r1.redirect(String::from("/next\r\nContent-Length: 0\r\n\r\nHTTP/1.1 200 OK"), RedirectKind::Found);
+        let output = r1.to_string();
+        assert_eq!(output.matches("\r\n\r\n").count(), 1);
+        assert!(output.contains("Location: /next  Content-Length: 0    HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn test_status_builders() {
+        let mut r1 = HttpResponse::new();
+        r1.body_str(String::from("gone")).no_content();
+        r1.header(String::from("Date"), String::from("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(r1.to_string(), "HTTP/1.1 204 No Content\r\nDate: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n");
+
+        let mut r2 = HttpResponse::new();
+        r2.redirect(String::from("/login"), RedirectKind::SeeOther);
+        assert_eq!(r2.status, StatusCode::SEE_OTHER);
+        assert_eq!(r2.headers.get("Location"), Some("/login"));
+
+        let mut r3 = HttpResponse::new();
+        r3.unauthorized(String::from("Basic realm=\"app\""));
+        assert_eq!("HTTP/1.1 401 Unauthorized", r3.to_string().lines().next().unwrap());
+        assert_eq!(r3.headers.get("WWW-Authenticate"), Some("Basic realm=\"app\""));
+
+        assert_eq!(HttpResponse::new().created().status.as_u16(), 201);
+        assert_eq!(HttpResponse::new().forbidden().body_str_ref(), Some("403 Forbidden"));
+        assert!(HttpResponse::new().conflict().status.is_client_error());
+    }
+
+    #[test]
+    fn test_write_to() {
+        let mut r1 = HttpResponse::new();
+        r1.body(vec![0x89, b'P', b'N', b'G', 0x00, 0xff]);
+        let mut output = Vec::new();
+        r1.write_to(&mut output).unwrap();
+        // the Date header is added while writing, its value is taken from the output
+        let date = String::from_utf8_lossy(&output).lines().find(|it| it.starts_with("Date: ")).unwrap().to_string();
+        assert!(parse_http_date(&date["Date: ".len()..]).is_some());
+        let mut expected = format!("HTTP/1.1 200 OK\r\n{}\r\nContent-Length: 6\r\n\r\n", date).into_bytes();
+        expected.extend_from_slice(&[0x89, b'P', b'N', b'G', 0x00, 0xff]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_streamed_body() {
+        let mut r1 = HttpResponse::new();
+        r1.body_stream(Body::reader("a,b\n1,2\n".as_bytes()));
+        r1.trailers.insert(String::from("X-Rows"), String::from("2"));
+        let mut output = Vec::new();
+        r1.write_to(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Transfer-Encoding: chunked\r\nTrailer: X-Rows\r\n\r\n"));
+        assert!(output.ends_with("\r\n\r\n8\r\na,b\n1,2\n\r\n0\r\nX-Rows: 2\r\n\r\n"));
+        assert!(!output.contains("Content-Length"));
+
+        let mut rows = (1..=2).map(|it| Ok(format!("{}\n", it).into_bytes()));
+        let mut r2 = HttpResponse::new();
+        r2.version = HttpVersion::V1_0;
+        r2.body_stream(Body::from_fn(move || rows.next()));
+        let mut output = Vec::new();
+        r2.write_to(&mut output).unwrap();
+        assert!(output.ends_with(b"Connection: close\r\n\r\n1\n2\n"));
+        assert!(!r2.keep_alive());
+
+        let mut r3 = HttpResponse::new();
+        r3.body_stream(Body::sized_reader("hello".as_bytes(), 5));
+        let mut output = Vec::new();
+        r3.write_to(&mut output).unwrap();
+        assert!(output.ends_with(b"Content-Length: 5\r\n\r\nhello"));
+        let mut r4 = HttpResponse::new();
+        r4.body_stream(Body::sized_reader("hello".as_bytes(), 6));
+        assert!(r4.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_failed_body() {
+        let mut chunks = vec![Err(io::Error::other("disk error")), Ok(b"a".to_vec())];
+        let mut r1 = HttpResponse::new();
+        r1.body_stream(Body::from_fn(move || chunks.pop()));
+        let mut output = Vec::new();
+        assert!(r1.write_to(&mut output).is_err());
+        assert!(output.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        assert!(r1.head_sent());
+        assert!(!r1.keep_alive());
+
+        let r2 = HttpResponse::new();
+        assert!(!r2.head_sent());
+        assert!(r2.keep_alive());
+    }
+
+    #[test]
+    fn test_write_without_body() {
+        let mut r1 = HttpResponse::for_request(&HttpRequest::new("HEAD / HTTP/1.1\n\n").unwrap(), None);
+        r1.body_str(String::from("hello"));
+        let mut output = Vec::new();
+        r1.write_to(&mut output).unwrap();
+        assert!(output.ends_with(b"Content-Length: 5\r\n\r\n"));
+
+        let mut r2 = HttpResponse::for_request(&HttpRequest::new("HEAD / HTTP/1.1\n\n").unwrap(), None);
+        r2.body_stream(Body::reader("hello".as_bytes()));
+        let mut output = Vec::new();
+        r2.write_to(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with("\r\n\r\n") && !output.contains("hello"));
+
+        let mut r3 = HttpResponse::new();
+        r3.status(StatusCode::NOT_MODIFIED).body_str(String::from("hello"));
+        let mut output = Vec::new();
+        r3.write_to(&mut output).unwrap();
+        assert!(output.ends_with(b"Content-Length: 5\r\n\r\n"));
+
+        let mut r4 = HttpResponse::new();
+        r4.status(StatusCode::NO_CONTENT).body_str(String::from("hello"));
+        let mut output = Vec::new();
+        r4.write_to(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with("\r\n\r\n") && !output.contains("Content-Length") && !output.contains("hello"));
+    }
+}
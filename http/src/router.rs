@@ -1,12 +1,39 @@
 use std::error::Error;
 use regex::{Regex};
 use crate::channel::HttpChannel;
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+use crate::error::HttpError;
 use crate::request::{HttpMethod, HttpRequest};
+use crate::response::{HttpResponse, DEFAULT_SERVER_NAME};
 
 pub trait RequestMapping {
     fn predicate(&self, http_request: &HttpRequest) -> bool;
 
     fn handle(&self, http_channel: &mut HttpChannel) -> Result<(), Box<dyn Error>>;
+
+    /// Upper bound for the body of matched requests, `None` keeps the server limit.
+    fn max_body_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Decides before the body is read whether the request is accepted, e.g. by its
+    /// credentials or its `Content-Length`. A rejected request is answered with the error.
+    fn guard(&self, _http_request: &HttpRequest) -> Result<(), HttpError> {
+        Ok(())
+    }
+
+    /// Whether the handler reads the body from the connection itself, with
+    /// `HttpChannel::body_reader` or `HttpChannel::multipart`, instead of getting it buffered.
+    fn stream_body(&self) -> bool {
+        false
+    }
+
+    /// Compression of the responses of this mapping, `None` keeps the router setting.
+    #[cfg(feature = "compression")]
+    fn compression(&self) -> Option<&Compression> {
+        None
+    }
 }
 
 pub enum RegexMapping<F>
@@ -14,43 +41,198 @@ pub enum RegexMapping<F>
 {
     // matches GET requests
     GET(Regex, F),
+    // matches HEAD requests
+    HEAD(Regex, F),
     // matches POST requests
     POST(Regex, F),
+    // matches PUT requests
+    PUT(Regex, F),
+    // matches DELETE requests
+    DELETE(Regex, F),
+    // matches CONNECT requests
+    CONNECT(Regex, F),
+    // matches OPTIONS requests
+    OPTIONS(Regex, F),
+    // matches TRACE requests
+    TRACE(Regex, F),
+    // matches PATCH requests
+    PATCH(Regex, F),
+    // matches requests with any of the given methods, including extension methods
+    METHODS(Vec<HttpMethod>, Regex, F),
     // matches ALL requests
     REQUEST(Regex, F),
 }
 
+impl<F> RegexMapping<F>
+    where F: Fn(&mut HttpChannel) -> Result<(), Box<dyn Error>>
+{
+    fn method_match(&self, method: &HttpMethod) -> bool {
+        match self {
+            // a GET route also answers HEAD, the response is sent without its body
+            RegexMapping::GET(..) => *method == HttpMethod::GET || *method == HttpMethod::HEAD,
+            RegexMapping::HEAD(..) => *method == HttpMethod::HEAD,
+            RegexMapping::POST(..) => *method == HttpMethod::POST,
+            RegexMapping::PUT(..) => *method == HttpMethod::PUT,
+            RegexMapping::DELETE(..) => *method == HttpMethod::DELETE,
+            RegexMapping::CONNECT(..) => *method == HttpMethod::CONNECT,
+            RegexMapping::OPTIONS(..) => *method == HttpMethod::OPTIONS,
+            RegexMapping::TRACE(..) => *method == HttpMethod::TRACE,
+            RegexMapping::PATCH(..) => *method == HttpMethod::PATCH,
+            RegexMapping::METHODS(methods, ..) => methods.contains(method),
+            RegexMapping::REQUEST(..) => true,
+        }
+    }
+
+    fn regex_and_handler(&self) -> (&Regex, &F) {
+        match self {
+            RegexMapping::GET(regex, f)
+            | RegexMapping::HEAD(regex, f)
+            | RegexMapping::POST(regex, f)
+            | RegexMapping::PUT(regex, f)
+            | RegexMapping::DELETE(regex, f)
+            | RegexMapping::CONNECT(regex, f)
+            | RegexMapping::OPTIONS(regex, f)
+            | RegexMapping::TRACE(regex, f)
+            | RegexMapping::PATCH(regex, f)
+            | RegexMapping::METHODS(_, regex, f)
+            | RegexMapping::REQUEST(regex, f) => (regex, f),
+        }
+    }
+}
+
 impl<F> RequestMapping for RegexMapping<F>
     where F: Fn(&mut HttpChannel) -> Result<(), Box<dyn Error>>
 {
     fn predicate(&self, http_request: &HttpRequest) -> bool {
-        match self {
-            RegexMapping::GET(regex, _)
-            if http_request.method == HttpMethod::GET && http_request.path_match(regex) => true,
-            RegexMapping::POST(regex, _)
-            if http_request.method == HttpMethod::POST && http_request.path_match(regex) => true,
-            RegexMapping::REQUEST(regex, _)
-            if http_request.path_match(regex) => true,
-            _ => false,
+        self.method_match(&http_request.method) && http_request.path_match(self.regex_and_handler().0)
+    }
+
+    fn handle(&self, http_channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
+        (self.regex_and_handler().1)(http_channel)
+    }
+}
+
+type Guard = Box<dyn Fn(&HttpRequest) -> Result<(), HttpError> + Send + Sync>;
+
+/// Wraps a mapping to give it its own settings.
+pub struct Route<M: RequestMapping> {
+    mapping: M,
+    max_body_size: Option<usize>,
+    guard: Option<Guard>,
+    stream_body: bool,
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
+}
+
+impl<M: RequestMapping> Route<M> {
+    pub fn new(mapping: M) -> Self {
+        Route {
+            mapping, max_body_size: None, guard: None, stream_body: false,
+            #[cfg(feature = "compression")]
+            compression: None,
         }
     }
 
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// Checks the request head before the body is read, in addition to the mapping's own guard.
+    pub fn guard<G>(mut self, guard: G) -> Self
+        where G: Fn(&HttpRequest) -> Result<(), HttpError> + Send + Sync + 'static {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Hands the body to the handler as it arrives instead of buffering it, for large uploads.
+    pub fn stream_body(mut self, stream_body: bool) -> Self {
+        self.stream_body = stream_body;
+        self
+    }
+
+    #[cfg(feature = "compression")]
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+}
+
+impl<M: RequestMapping> RequestMapping for Route<M> {
+    fn predicate(&self, http_request: &HttpRequest) -> bool {
+        self.mapping.predicate(http_request)
+    }
+
     fn handle(&self, http_channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
-        match self {
-            RegexMapping::GET(_, f) => f(http_channel),
-            RegexMapping::POST(_, f) => f(http_channel),
-            RegexMapping::REQUEST(_, f) => f(http_channel),
+        self.mapping.handle(http_channel)
+    }
+
+    fn max_body_size(&self) -> Option<usize> {
+        self.max_body_size.or_else(|| self.mapping.max_body_size())
+    }
+
+    fn guard(&self, http_request: &HttpRequest) -> Result<(), HttpError> {
+        if let Some(guard) = &self.guard {
+            guard(http_request)?;
         }
+        self.mapping.guard(http_request)
+    }
+
+    fn stream_body(&self) -> bool {
+        self.stream_body || self.mapping.stream_body()
+    }
+
+    #[cfg(feature = "compression")]
+    fn compression(&self) -> Option<&Compression> {
+        self.compression.as_ref().or_else(|| self.mapping.compression())
     }
 }
 
 pub struct HttpRouter<'b> {
     mappings: Vec<Box<dyn RequestMapping + Send + Sync + 'b>>,
+    // the `Server` header of the responses, `None` leaves it out
+    server_name: Option<String>,
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
+}
+
+impl<'b> Default for HttpRouter<'b> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'b> HttpRouter<'b> {
     pub fn new() -> Self {
-        HttpRouter { mappings: Vec::new() }
+        HttpRouter {
+            mappings: Vec::new(),
+            server_name: Some(String::from(DEFAULT_SERVER_NAME)),
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
+
+    /// Compresses the responses of all routes that don't set their own compression.
+    #[cfg(feature = "compression")]
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Sets the `Server` header sent with the responses, `None` leaves it out so the
+    /// software isn't disclosed.
+    pub fn server_name(&mut self, server_name: Option<String>) -> &mut Self {
+        self.server_name = server_name;
+        self
+    }
+
+    pub fn server_name_ref(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Creates the response to the request, see `HttpResponse::for_request`.
+    pub fn response(&self, http_request: &HttpRequest) -> HttpResponse {
+        HttpResponse::for_request(http_request, self.server_name_ref())
     }
 
     pub fn route(&mut self, request_mapping: Box<dyn RequestMapping + Send + Sync + 'b>) -> &mut Self {
@@ -58,13 +240,47 @@ impl<'b> HttpRouter<'b> {
         self
     }
 
+    /// The body limit of the mapping that will handle the request, read before its body.
+    pub fn max_body_size(&self, http_request: &HttpRequest) -> Option<usize> {
+        self.mapping(http_request).and_then(|it| it.max_body_size())
+    }
+
+    /// Whether the mapping that will handle the request reads the body itself, see `RequestMapping::stream_body`.
+    pub fn stream_body(&self, http_request: &HttpRequest) -> bool {
+        self.mapping(http_request).is_some_and(|it| it.stream_body())
+    }
+
+    /// Asks the mapping that will handle the request whether to accept it, before its body is read.
+    pub fn guard(&self, http_request: &HttpRequest) -> Result<(), HttpError> {
+        match self.mapping(http_request) {
+            Some(mapping) => mapping.guard(http_request),
+            None => Ok(()),
+        }
+    }
+
+    // the first mapping that takes the request
+    fn mapping(&self, http_request: &HttpRequest) -> Option<&(dyn RequestMapping + Send + Sync + 'b)> {
+        self.mappings.iter().find(|it| it.predicate(http_request)).map(|it| it.as_ref())
+    }
+
     pub fn handle(&self, http_channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
-        self.mappings.iter().find(|it| it.predicate(http_channel.request))
-            .map(|it| it.handle(http_channel))
-            .unwrap_or_else(|| {
+        let mapping = match self.mapping(http_channel.request) {
+            Some(mapping) => mapping,
+            None => {
                 http_channel.response.not_found();
-                Ok(())
-            })
+                return Ok(());
+            }
+        };
+        #[cfg(feature = "compression")]
+        {
+            http_channel.compression = mapping.compression().or(self.compression.as_ref()).cloned();
+        }
+        mapping.handle(http_channel)?;
+        #[cfg(feature = "compression")]
+        if !http_channel.is_sent {
+            http_channel.compress()?;
+        }
+        Ok(())
     }
 }
 
@@ -73,8 +289,10 @@ impl<'b> HttpRouter<'b> {
 mod test {
     use super::*;
     use crate::request::*;
-    use crate::response::HttpResponse;
+    use crate::status::StatusCode;
     use crate::utils::get_stream;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
 
     #[test]
     fn test1() {
@@ -90,14 +308,146 @@ mod test {
                                                     channel.response.body_str(String::from("world"));
                                                     Ok(())
                                                 })));
-        let http_request: HttpRequest = HttpRequest::new("GET /hello HTTP/1.1\n\n");
+        let http_request: HttpRequest = HttpRequest::new("GET /hello HTTP/1.1\n\n").unwrap();
         let mut http_response = HttpResponse::new();
         let mut stream = get_stream(8090);
         let mut channel = HttpChannel::new(&http_request, &mut http_response, &mut stream);
 
         router.handle(&mut channel).unwrap();
         let response = channel.response;
-        assert_eq!("hello", response.body_str_ref().unwrap())
+        assert_eq!("hello", response.body_str_ref().unwrap());
+
+        // a HEAD request runs the GET route
+        let http_request: HttpRequest = HttpRequest::new("HEAD /world HTTP/1.1\n\n").unwrap();
+        let mut http_response = router.response(&http_request);
+        let mut channel = HttpChannel::new(&http_request, &mut http_response, &mut stream);
+        router.handle(&mut channel).unwrap();
+        assert_eq!(Some("world"), channel.response.body_str_ref());
+    }
+
+    #[test]
+    fn test_methods() {
+        let mut router = HttpRouter::new();
+        router.route(Box::new(RegexMapping::DELETE(Regex::new(r"^/item$").unwrap(),
+                                                   |channel| {
+                                                       channel.response.body_str(String::from("deleted"));
+                                                       Ok(())
+                                                   })));
+        router.route(Box::new(RegexMapping::METHODS(vec![HttpMethod::PUT, HttpMethod::from("PROPFIND")],
+                                                    Regex::new(r"^/item$").unwrap(),
+                                                    |channel| {
+                                                        channel.response.body_str(channel.request.method.to_string());
+                                                        Ok(())
+                                                    })));
+        let mut stream = get_stream(8091);
+        for (request, body) in [("DELETE /item HTTP/1.1\n\n", Some("deleted")),
+                                ("PUT /item HTTP/1.1\n\n", Some("PUT")),
+                                ("PROPFIND /item HTTP/1.1\n\n", Some("PROPFIND")),
+                                ("PATCH /item HTTP/1.1\n\n", Some("404 Not Found")),
+                                // HEAD falls back to the GET route, there is none for /item
+                                ("HEAD /item HTTP/1.1\n\n", Some("404 Not Found"))] {
+            let http_request: HttpRequest = HttpRequest::new(request).unwrap();
+            let mut http_response = HttpResponse::new();
+            let mut channel = HttpChannel::new(&http_request, &mut http_response, &mut stream);
+            router.handle(&mut channel).unwrap();
+            assert_eq!(body, channel.response.body_str_ref());
+        }
+    }
+
+    #[test]
+    fn test_route_max_body_size() {
+        let mut router = HttpRouter::new();
+        router.route(Box::new(Route::new(RegexMapping::POST(Regex::new(r"^/upload$").unwrap(), |_| Ok(())))
+            .max_body_size(1024 * 1024)));
+        router.route(Box::new(RegexMapping::POST(Regex::new(r"^/").unwrap(), |_| Ok(()))));
+        let upload = HttpRequest::new("POST /upload HTTP/1.1\n\n").unwrap();
+        assert_eq!(router.max_body_size(&upload), Some(1024 * 1024));
+        let other = HttpRequest::new("POST /other HTTP/1.1\n\n").unwrap();
+        assert_eq!(router.max_body_size(&other), None);
+    }
+
+    #[test]
+    fn test_head_event_stream() {
+        let mut router = HttpRouter::new();
+        router.route(Box::new(RegexMapping::GET(Regex::new(r"^/events$").unwrap(),
+                                                |channel| {
+                                                    let mut events = channel.event_stream()?;
+                                                    events.send(None, "hello", None)?;
+                                                    events.close()?;
+                                                    Ok(())
+                                                })));
+        let listener = TcpListener::bind("127.0.0.1:8094").unwrap();
+        let mut client = TcpStream::connect("127.0.0.1:8094").unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let http_request = HttpRequest::new("HEAD /events HTTP/1.1\n\n").unwrap();
+        let mut http_response = router.response(&http_request);
+        let mut channel = HttpChannel::new(&http_request, &mut http_response, &mut stream);
+        router.handle(&mut channel).unwrap();
+        assert!(channel.is_sent);
+        drop(stream);
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.ends_with("\r\n\r\n") && !output.contains("hello"));
+    }
+
+    #[test]
+    fn test_server_name() {
+        let http_request = HttpRequest::new("GET / HTTP/1.1\n\n").unwrap();
+        let mut router = HttpRouter::new();
+        assert_eq!(Some("Rust Server/1.0"), router.response(&http_request).headers.get("Server"));
+        router.server_name(Some(String::from("edge")));
+        assert_eq!(Some("edge"), router.response(&http_request).headers.get("Server"));
+        router.server_name(None);
+        assert_eq!(None, router.response(&http_request).headers.get("Server"));
+    }
+
+    #[test]
+    fn test_route_stream_body() {
+        let mut router = HttpRouter::new();
+        router.route(Box::new(Route::new(RegexMapping::POST(Regex::new(r"^/upload$").unwrap(), |_| Ok(())))
+            .stream_body(true)));
+        router.route(Box::new(RegexMapping::POST(Regex::new(r"^/").unwrap(), |_| Ok(()))));
+        assert!(router.stream_body(&HttpRequest::new("POST /upload HTTP/1.1\nHost: a\n\n").unwrap()));
+        assert!(!router.stream_body(&HttpRequest::new("POST /other HTTP/1.1\nHost: a\n\n").unwrap()));
+    }
+
+    #[test]
+    fn test_route_guard() {
+        let mut router = HttpRouter::new();
+        router.route(Box::new(Route::new(RegexMapping::POST(Regex::new(r"^/upload$").unwrap(), |_| Ok(())))
+            .guard(|request| match request.header("Authorization") {
+                Some(_) => Ok(()),
+                None => Err(HttpError::new(StatusCode::UNAUTHORIZED, String::from("login required"))),
+            })));
+        let anonymous = HttpRequest::new("POST /upload HTTP/1.1\nHost: a\n\n").unwrap();
+        assert_eq!(router.guard(&anonymous).unwrap_err().status, StatusCode::UNAUTHORIZED);
+        let authorized = HttpRequest::new("POST /upload HTTP/1.1\nHost: a\nAuthorization: Basic YTpi\n\n").unwrap();
+        assert!(router.guard(&authorized).is_ok());
+        let other = HttpRequest::new("POST /other HTTP/1.1\nHost: a\n\n").unwrap();
+        assert!(router.guard(&other).is_ok());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_route_compression() {
+        let mut router = HttpRouter::new();
+        router.route(Box::new(Route::new(RegexMapping::GET(Regex::new(r"^/big$").unwrap(), |channel| {
+            channel.response.body_str("x".repeat(4096));
+            Ok(())
+        })).compression(Compression::default())));
+        router.route(Box::new(RegexMapping::GET(Regex::new(r"^/plain$").unwrap(), |channel| {
+            channel.response.body_str("x".repeat(4096));
+            Ok(())
+        })));
+        let mut stream = get_stream(8092);
+        for (path, encoding) in [("/big", Some("gzip")), ("/plain", None)] {
+            let http_request = HttpRequest::new(format!("GET {} HTTP/1.1\nHost: a\nAccept-Encoding: gzip\n\n", path)).unwrap();
+            let mut http_response = HttpResponse::new();
+            let mut channel = HttpChannel::new(&http_request, &mut http_response, &mut stream);
+            router.handle(&mut channel).unwrap();
+            assert_eq!(channel.response.headers.get("Content-Encoding"), encoding);
+        }
     }
 
     #[test]
@@ -0,0 +1,149 @@
+use std::io::{self, ErrorKind, Write};
+use std::time::Duration;
+use crate::chunked::ChunkedWriter;
+
+enum Framing<W: Write> {
+    // HTTP/1.1, the connection can be reused after the stream ends
+    Chunked(ChunkedWriter<W>),
+    // HTTP/1.0, the stream ends with the connection
+    Close(W),
+    // the response to HEAD has no body, the events are dropped
+    Discard,
+}
+
+/// Writes Server-Sent Events to a response that stays open, created with `HttpChannel::event_stream`.
+/// Every write is flushed, an error means the client is gone (see `is_disconnect`).
+pub struct EventStream<W: Write> {
+    framing: Option<Framing<W>>,
+    last_event_id: Option<String>,
+}
+
+impl<W: Write> EventStream<W> {
+    pub(crate) fn new(writer: W, chunked: bool, last_event_id: Option<String>) -> Self {
+        let framing = if chunked { Framing::Chunked(ChunkedWriter::new(writer)) } else { Framing::Close(writer) };
+        EventStream { framing: Some(framing), last_event_id }
+    }
+
+    // a stream for a HEAD request, nothing is written after the head
+    pub(crate) fn discard(last_event_id: Option<String>) -> Self {
+        EventStream { framing: Some(Framing::Discard), last_event_id }
+    }
+
+    /// The `Last-Event-ID` a reconnecting client sent, events after it should be sent again.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Sends an event, `data` may span several lines. Without an event name the client
+    /// dispatches a `message` event, the id is sent back as `Last-Event-ID` on reconnect.
+    pub fn send(&mut self, event: Option<&str>, data: &str, id: Option<&str>) -> io::Result<()> {
+        let mut message = String::new();
+        if let Some(event) = event {
+            message.push_str(&format!("event: {}\n", single_line(event)?));
+        }
+        if let Some(id) = id {
+            if id.contains('\0') {
+                return Err(io::Error::new(ErrorKind::InvalidInput, "event id contains NUL"));
+            }
+            message.push_str(&format!("id: {}\n", single_line(id)?));
+        }
+        // the client ends a line at CRLF, a lone CR or a lone LF, each line gets its own field
+        for line in data.split("\r\n").flat_map(|it| it.split(['\r', '\n'])) {
+            message.push_str(&format!("data: {}\n", line));
+        }
+        message.push('\n');
+        self.write(message.as_bytes())
+    }
+
+    /// Sends a comment line the client ignores. Sent periodically it keeps proxies from
+    /// closing an idle stream and notices a disconnected client.
+    pub fn keep_alive(&mut self) -> io::Result<()> {
+        self.write(b": keep-alive\n\n")
+    }
+
+    /// Tells the client how long to wait before reconnecting.
+    pub fn retry(&mut self, delay: Duration) -> io::Result<()> {
+        self.write(format!("retry: {}\n\n", delay.as_millis()).as_bytes())
+    }
+
+    /// Ends the stream. Dropping it does the same, ignoring errors.
+    pub fn close(mut self) -> io::Result<()> {
+        self.finish()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let writer: &mut dyn Write = match &mut self.framing {
+            Some(Framing::Chunked(writer)) => writer,
+            Some(Framing::Close(writer)) => writer,
+            Some(Framing::Discard) => return Ok(()),
+            None => return Err(io::Error::new(ErrorKind::NotConnected, "event stream closed")),
+        };
+        writer.write_all(bytes)?;
+        writer.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.framing.take() {
+            Some(Framing::Chunked(writer)) => writer.finish(&[]).map(|_| ()),
+            Some(Framing::Close(mut writer)) => writer.flush(),
+            Some(Framing::Discard) | None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for EventStream<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Whether a write error means the client closed the connection.
+pub fn is_disconnect(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::NotConnected)
+}
+
+fn single_line(value: &str) -> io::Result<&str> {
+    if value.contains(['\r', '\n']) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "event field contains a line break"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_send() {
+        let mut output = Vec::new();
+        let mut events = EventStream::new(&mut output, false, Some(String::from("41")));
+        assert_eq!(events.last_event_id(), Some("41"));
+        events.send(Some("build"), "step 1\nstep 2", Some("42")).unwrap();
+        events.send(None, "done", None).unwrap();
+        events.keep_alive().unwrap();
+        events.retry(Duration::from_secs(3)).unwrap();
+        assert!(events.send(Some("a\nb"), "", None).is_err());
+        events.close().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "event: build\nid: 42\ndata: step 1\ndata: step 2\n\ndata: done\n\n: keep-alive\n\nretry: 3000\n\n");
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let mut output = Vec::new();
+        let mut events = EventStream::new(&mut output, false, None);
+        events.send(None, "a\r\nb\rid: 1\nc\r\r\nd", None).unwrap();
+        events.close().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "data: a\ndata: b\ndata: id: 1\ndata: c\ndata: \ndata: d\n\n");
+    }
+
+    #[test]
+    fn test_chunked_stream() {
+        let mut output = Vec::new();
+        {
+            let mut events = EventStream::new(&mut output, true, None);
+            events.send(None, "hi", None).unwrap();
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), "a\r\ndata: hi\n\n\r\n0\r\n\r\n");
+    }
+}
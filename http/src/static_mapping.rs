@@ -10,6 +10,12 @@ pub struct StaticMapping {
     static_path: Vec<Regex>,
 }
 
+impl Default for StaticMapping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StaticMapping {
     pub fn new() -> Self {
         Self {
@@ -20,7 +26,7 @@ impl StaticMapping {
 
 impl RequestMapping for StaticMapping {
     fn predicate(&self, http_request: &HttpRequest) -> bool {
-        http_request.method == HttpMethod::GET
+        (http_request.method == HttpMethod::GET || http_request.method == HttpMethod::HEAD)
             && self.static_path.iter().find(|it| http_request.path_match(it)).is_some()
     }
 
@@ -30,7 +36,7 @@ impl RequestMapping for StaticMapping {
         if path.ends_with("/") {
             path += "index.html";
         }
-        match File::open(&path.replace("/",&std::path::MAIN_SEPARATOR.to_string())).or_else(|_| File::open(format!("{}.html", base_path))) {
+        match File::open(path.replace('/', std::path::MAIN_SEPARATOR_STR)).or_else(|_| File::open(format!("{}.html", base_path))) {
             Ok(mut file) => channel.send(&file.read_all_vec()?)?,
             Err(_) => { channel.response.not_found().body(format!("resource not found: {}", path).clone().into()); }
         };
@@ -52,7 +58,7 @@ mod test {
 
     #[test]
     fn test_static_mapping() {
-        let http_request: HttpRequest = HttpRequest::new("GET /static/hello.html HTTP/1.1\n\n");
+        let http_request: HttpRequest = HttpRequest::new("GET /static/hello.html HTTP/1.1\n\n").unwrap();
         let mut http_response = HttpResponse::new();
         let listener = TcpListener::bind("127.0.0.1:8081").unwrap();
         let mut stream = TcpStream::connect("127.0.0.1:8081").unwrap();
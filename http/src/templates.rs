@@ -0,0 +1,150 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+use minijinja::{path_loader, Environment, Error, ErrorKind};
+use serde::Serialize;
+
+// the templates used by `HttpResponse::render`
+static TEMPLATES: RwLock<Option<Templates>> = RwLock::new(None);
+
+/// Templates loaded from a directory, e.g. `templates/` next to `static/`. Templates
+/// ending in `.html`, `.htm` or `.xml` escape their values, `{% extends %}` and
+/// `{% include %}` name other templates relative to the directory.
+pub struct Templates {
+    env: Environment<'static>,
+    dir: PathBuf,
+    // newest modification time seen under the directory, `None` when not reloading
+    modified: Option<SystemTime>,
+}
+
+impl Templates {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let mut env = Environment::new();
+        env.set_loader(path_loader(&dir));
+        Templates { env, dir, modified: None }
+    }
+
+    /// Checks the directory for changed templates before each render, for development.
+    pub fn reload(&mut self, reload: bool) -> &mut Self {
+        self.modified = if reload { Some(newest_modification(&self.dir)) } else { None };
+        self
+    }
+
+    /// The environment, to add filters, functions or globals.
+    pub fn environment_mut(&mut self) -> &mut Environment<'static> {
+        &mut self.env
+    }
+
+    pub fn render<S: Serialize>(&self, name: &str, context: S) -> Result<String, Error> {
+        self.env.get_template(name)?.render(context)
+    }
+
+    // drops the loaded templates if a file changed since the last check
+    fn refresh(&mut self) {
+        if let Some(modified) = self.modified {
+            let newest = newest_modification(&self.dir);
+            if newest != modified {
+                self.env.clear_templates();
+                self.modified = Some(newest);
+            }
+        }
+    }
+}
+
+/// Sets the templates `HttpResponse::render` uses, usually once at startup.
+pub fn set_templates(templates: Templates) {
+    *TEMPLATES.write().unwrap_or_else(|e| e.into_inner()) = Some(templates);
+}
+
+/// Renders a template of the templates set with `set_templates`.
+pub fn render<S: Serialize>(name: &str, context: S) -> Result<String, Error> {
+    let reload = TEMPLATES.read().unwrap_or_else(|e| e.into_inner()).as_ref().is_some_and(|it| it.modified.is_some());
+    if reload {
+        if let Some(templates) = TEMPLATES.write().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            templates.refresh();
+        }
+    }
+    match TEMPLATES.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(templates) => templates.render(name, context),
+        None => Err(Error::new(ErrorKind::InvalidOperation, "no templates set, see templates::set_templates")),
+    }
+}
+
+fn newest_modification(dir: &Path) -> SystemTime {
+    let mut newest = SystemTime::UNIX_EPOCH;
+    let _ = visit_files(dir, &mut |modified| newest = newest.max(modified));
+    newest
+}
+
+fn visit_files(dir: &Path, f: &mut dyn FnMut(SystemTime)) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            visit_files(&entry.path(), f)?;
+        } else {
+            f(metadata.modified()?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::time::Duration;
+    use minijinja::context;
+    use crate::response::HttpResponse;
+    use super::*;
+
+    fn template_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("http-templates-{}-{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("partials")).unwrap();
+        fs::write(dir.join("layout.html"), "<title>{{ title }}</title>{% block content %}{% endblock %}").unwrap();
+        fs::write(dir.join("partials/item.html"), "<li>{{ item }}</li>").unwrap();
+        fs::write(dir.join("page.html"),
+                  "{% extends \"layout.html\" %}{% block content %}<ul>{% for item in items %}{% include \"partials/item.html\" %}{% endfor %}</ul>{% endblock %}").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_render() {
+        let dir = template_dir("render");
+        let templates = Templates::new(&dir);
+        let page = context! { title => "Users & Groups", items => ["<script>"] };
+        assert_eq!(templates.render("page.html", &page).unwrap(),
+                   "<title>Users &amp; Groups</title><ul><li>&lt;script&gt;</li></ul>");
+        assert!(templates.render("missing.html", &page).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_response_render() {
+        let dir = template_dir("response");
+        set_templates(Templates::new(&dir));
+        let mut http_response = HttpResponse::new();
+        http_response.render("layout.html", context! { title => "admin" }).unwrap();
+        assert_eq!(http_response.headers.get("Content-Type"), Some("text/html; charset=utf-8"));
+        assert_eq!(http_response.body_str_ref(), Some("<title>admin</title>"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = template_dir("reload");
+        let mut templates = Templates::new(&dir);
+        templates.reload(true);
+        let page = context! { title => "a" };
+        assert_eq!(templates.render("layout.html", &page).unwrap(), "<title>a</title>");
+        fs::write(dir.join("layout.html"), "<h1>{{ title }}</h1>").unwrap();
+        // make the change visible on file systems with coarse timestamps
+        let file = fs::File::options().write(true).open(dir.join("layout.html")).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        templates.refresh();
+        assert_eq!(templates.render("layout.html", &page).unwrap(), "<h1>a</h1>");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -10,6 +10,11 @@ pub fn get_stream(port: i32) -> TcpStream {
     stream
 }
 
+/// Decodes bytes as ISO-8859-1, every byte maps to the char with the same code point.
+pub fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|it| *it as char).collect()
+}
+
 impl<T: Read> MyRead for T {
     fn read_all_string(&mut self) -> Result<String, Box<dyn Error>> {
         // Wrap the stream in a BufReader, so we can use the BufRead methods
@@ -0,0 +1,576 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use regex::Regex;
+use crate::channel::HttpChannel;
+use crate::error::HttpError;
+use crate::request::{HttpMethod, HttpRequest, HttpVersion};
+use crate::router::RequestMapping;
+use crate::status::StatusCode;
+
+// appended to the client key before hashing, RFC 6455 section 1.3
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The `Sec-WebSocket-Accept` value answering a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = sha1_smol::Sha1::new();
+    sha1.update(key.trim().as_bytes());
+    sha1.update(ACCEPT_GUID.as_bytes());
+    BASE64.encode(sha1.digest().bytes())
+}
+
+/// Checks that the request asks for a WebSocket upgrade, returns the `Sec-WebSocket-Key`.
+pub fn check_handshake(http_request: &HttpRequest) -> Result<&str, HttpError> {
+    let has_token = |name: &str, token: &str| http_request.headers.get_all(name)
+        .any(|it| it.split(',').any(|it| it.trim().eq_ignore_ascii_case(token)));
+    if http_request.method != HttpMethod::GET || http_request.version != HttpVersion::V1_1 {
+        return Err(HttpError::bad_request(String::from("a WebSocket handshake must be an HTTP/1.1 GET request")));
+    }
+    if !has_token("Upgrade", "websocket") || !has_token("Connection", "upgrade") {
+        return Err(HttpError::new(StatusCode::UPGRADE_REQUIRED, String::from("expected Upgrade: websocket")));
+    }
+    if http_request.header("Sec-WebSocket-Version").map(|it| it.trim()) != Some("13") {
+        return Err(HttpError::new(StatusCode::UPGRADE_REQUIRED, String::from("only Sec-WebSocket-Version 13 is supported")));
+    }
+    match http_request.header("Sec-WebSocket-Key") {
+        Some(key) if BASE64.decode(key.trim()).is_ok_and(|it| it.len() == 16) => Ok(key),
+        _ => Err(HttpError::bad_request(String::from("missing or invalid Sec-WebSocket-Key"))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // the status code and reason, if the peer sent one
+    Close(Option<(u16, String)>),
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    // the peer broke the protocol, the connection was closed with the given status
+    Protocol(u16, String),
+    // the connection was closed before, no more messages can be read or sent
+    Closed,
+    Io(io::Error),
+}
+
+impl fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebSocketError::Protocol(code, reason) => write!(f, "websocket protocol error {}: {}", code, reason),
+            WebSocketError::Closed => write!(f, "websocket closed"),
+            WebSocketError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl Error for WebSocketError {}
+
+impl From<io::Error> for WebSocketError {
+    fn from(e: io::Error) -> Self {
+        WebSocketError::Io(e)
+    }
+}
+
+// close status codes, RFC 6455 section 7.4.1
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    // receives masked frames and sends unmasked ones
+    Server,
+    // masks the frames it sends
+    Client,
+}
+
+/// A WebSocket connection after the handshake. Pings are answered and a close from the
+/// peer is echoed automatically, both are still returned by `read_message`.
+pub struct WebSocket<S: Read + Write> {
+    stream: S,
+    role: Role,
+    // longest message accepted, fragments together
+    max_message_size: usize,
+    // longer messages are sent in fragments of this size
+    max_frame_size: usize,
+    // opcode and payload of a fragmented message, control frames may arrive in between
+    fragments: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    pub fn new(stream: S, role: Role) -> Self {
+        WebSocket { stream, role, max_message_size: 16 * 1024 * 1024, max_frame_size: 64 * 1024, fragments: None, close_sent: false, close_received: false }
+    }
+
+    pub fn max_message_size(&mut self, max_message_size: usize) -> &mut Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    pub fn max_frame_size(&mut self, max_frame_size: usize) -> &mut Self {
+        self.max_frame_size = max_frame_size.max(1);
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Reads the next message, joining fragmented messages. A protocol violation closes the
+    /// connection with the matching status and is returned as `WebSocketError::Protocol`.
+    pub fn read_message(&mut self) -> Result<Message, WebSocketError> {
+        if self.close_received {
+            return Err(WebSocketError::Closed);
+        }
+        match self.read_message_frames() {
+            Err(WebSocketError::Protocol(code, reason)) => {
+                let _ = self.send_close(code, &reason);
+                self.close_received = true;
+                Err(WebSocketError::Protocol(code, reason))
+            }
+            result => result,
+        }
+    }
+
+    fn read_message_frames(&mut self) -> Result<Message, WebSocketError> {
+        loop {
+            let frame = self.read_frame()?;
+            match frame.opcode {
+                OP_PING => {
+                    self.write_frame(true, OP_PONG, &frame.payload)?;
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OP_PONG => return Ok(Message::Pong(frame.payload)),
+                OP_CLOSE => return self.receive_close(&frame.payload),
+                OP_TEXT | OP_BINARY if self.fragments.is_some() => {
+                    return Err(protocol_error("new message inside a fragmented message"));
+                }
+                OP_TEXT | OP_BINARY if frame.fin => return message(frame.opcode, frame.payload),
+                OP_TEXT | OP_BINARY => self.fragments = Some((frame.opcode, frame.payload)),
+                OP_CONTINUATION => {
+                    let (opcode, mut payload) = self.fragments.take().ok_or_else(|| protocol_error("continuation without a message"))?;
+                    if payload.len() + frame.payload.len() > self.max_message_size {
+                        return Err(WebSocketError::Protocol(CLOSE_TOO_BIG, String::from("message too big")));
+                    }
+                    payload.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return message(opcode, payload);
+                    }
+                    self.fragments = Some((opcode, payload));
+                }
+                opcode => return Err(protocol_error(&format!("unknown opcode {}", opcode))),
+            }
+        }
+    }
+
+    fn receive_close(&mut self, payload: &[u8]) -> Result<Message, WebSocketError> {
+        let close = match payload.len() {
+            0 => None,
+            1 => return Err(protocol_error("close payload of one byte")),
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                if !is_valid_close_code(code) {
+                    return Err(protocol_error("invalid close code"));
+                }
+                let reason = str::from_utf8(&payload[2..])
+                    .map_err(|_| WebSocketError::Protocol(CLOSE_INVALID_DATA, String::from("close reason is not UTF-8")))?;
+                Some((code, reason.to_string()))
+            }
+        };
+        self.close_received = true;
+        if !self.close_sent {
+            let code = close.as_ref().map_or(CLOSE_NORMAL, |it| it.0);
+            self.send_close(code, "")?;
+        }
+        Ok(Message::Close(close))
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        if head[0] & 0x70 != 0 {
+            return Err(protocol_error("reserved bits set without an extension"));
+        }
+        let masked = head[1] & 0x80 != 0;
+        if masked != (self.role == Role::Server) {
+            return Err(protocol_error(if masked { "masked frame from the server" } else { "unmasked frame from the client" }));
+        }
+        let length = match head[1] & 0x7F {
+            126 => {
+                let mut length = [0; 2];
+                self.stream.read_exact(&mut length)?;
+                u16::from_be_bytes(length) as u64
+            }
+            127 => {
+                let mut length = [0; 8];
+                self.stream.read_exact(&mut length)?;
+                u64::from_be_bytes(length)
+            }
+            length => length as u64,
+        };
+        if opcode >= OP_CLOSE && (length > 125 || !fin) {
+            return Err(protocol_error("control frames must be short and not fragmented"));
+        }
+        if length > self.max_message_size as u64 {
+            return Err(WebSocketError::Protocol(CLOSE_TOO_BIG, String::from("message too big")));
+        }
+        let mut mask = [0; 4];
+        if masked {
+            self.stream.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; length as usize];
+        self.stream.read_exact(&mut payload)?;
+        if masked {
+            apply_mask(&mut payload, mask);
+        }
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<(), WebSocketError> {
+        match message {
+            Message::Text(text) => self.send_data(OP_TEXT, text.as_bytes()),
+            Message::Binary(data) => self.send_data(OP_BINARY, data),
+            Message::Ping(data) => self.send_control(OP_PING, data),
+            Message::Pong(data) => self.send_control(OP_PONG, data),
+            Message::Close(Some((code, reason))) => self.send_close(*code, reason),
+            Message::Close(None) => {
+                self.close_sent = true;
+                self.write_frame(true, OP_CLOSE, &[])
+            }
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<(), WebSocketError> {
+        self.send_data(OP_TEXT, text.as_bytes())
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError> {
+        self.send_data(OP_BINARY, data)
+    }
+
+    /// Starts the closing handshake, `read_message` returns the peer's close afterwards.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        self.send_close(code, reason)
+    }
+
+    fn send_close(&mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        let mut payload = code.to_be_bytes().to_vec();
+        // the reason is cut to fit a control frame
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        payload.extend_from_slice(&reason.as_bytes()[..end]);
+        self.send_control(OP_CLOSE, &payload)?;
+        self.close_sent = true;
+        Ok(())
+    }
+
+    fn send_control(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        if payload.len() > 125 {
+            return Err(WebSocketError::Io(io::Error::new(io::ErrorKind::InvalidInput, "control frame payload over 125 bytes")));
+        }
+        self.write_frame(true, opcode, payload)
+    }
+
+    fn send_data(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        let mut fragments = payload.chunks(self.max_frame_size).peekable();
+        if fragments.peek().is_none() {
+            return self.write_frame(true, opcode, &[]);
+        }
+        let mut opcode = opcode;
+        while let Some(fragment) = fragments.next() {
+            self.write_frame(fragments.peek().is_none(), opcode, fragment)?;
+            opcode = OP_CONTINUATION;
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, fin: bool, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        let mask_bit = if self.role == Role::Client { 0x80 } else { 0 };
+        match payload.len() {
+            length if length < 126 => frame.push(mask_bit | length as u8),
+            length if length <= u16::MAX as usize => {
+                frame.push(mask_bit | 126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(mask_bit | 127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        let start = frame.len();
+        if self.role == Role::Client {
+            let mask = masking_key();
+            frame.extend_from_slice(&mask);
+            frame.extend_from_slice(payload);
+            apply_mask(&mut frame[start + 4..], mask);
+        } else {
+            frame.extend_from_slice(payload);
+        }
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn message(opcode: u8, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+    match opcode {
+        OP_TEXT => String::from_utf8(payload).map(Message::Text)
+            .map_err(|_| WebSocketError::Protocol(CLOSE_INVALID_DATA, String::from("text message is not UTF-8"))),
+        _ => Ok(Message::Binary(payload)),
+    }
+}
+
+// codes a close frame may carry (RFC 6455, section 7.4), 1005, 1006 and 1015 are never sent
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
+}
+
+fn protocol_error(reason: &str) -> WebSocketError {
+    WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, reason.to_string())
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+static MASK_STATE: AtomicU64 = AtomicU64::new(0);
+
+// masks only keep proxies from interpreting the payload, they need not be cryptographically strong
+fn masking_key() -> [u8; 4] {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |it| it.as_nanos() as u64);
+    let mut x = MASK_STATE.fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed) ^ nanos;
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    x ^= x >> 33;
+    (x as u32).to_be_bytes()
+}
+
+/// Routes WebSocket upgrades for matching paths. After the handshake the handler gets
+/// the connection and runs until it returns, then the connection is closed.
+pub struct WebSocketMapping<F>
+    where F: Fn(&HttpRequest, WebSocket<TcpStream>) -> Result<(), Box<dyn Error>>
+{
+    regex: Regex,
+    handler: F,
+}
+
+impl<F> WebSocketMapping<F>
+    where F: Fn(&HttpRequest, WebSocket<TcpStream>) -> Result<(), Box<dyn Error>>
+{
+    pub fn new(regex: Regex, handler: F) -> Self {
+        WebSocketMapping { regex, handler }
+    }
+}
+
+impl<F> RequestMapping for WebSocketMapping<F>
+    where F: Fn(&HttpRequest, WebSocket<TcpStream>) -> Result<(), Box<dyn Error>>
+{
+    fn predicate(&self, http_request: &HttpRequest) -> bool {
+        (http_request.method == HttpMethod::GET || http_request.method == HttpMethod::HEAD) && http_request.path_match(&self.regex)
+    }
+
+    fn handle(&self, http_channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
+        // HEAD gets the head of the answer to a GET without an upgrade, the connection stays HTTP
+        if http_channel.request.method == HttpMethod::HEAD {
+            http_channel.response.status(StatusCode::UPGRADE_REQUIRED)
+                .header(String::from("Upgrade"), String::from("websocket"));
+            return Ok(());
+        }
+        let key = check_handshake(http_channel.request)?;
+        http_channel.response.status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(String::from("Upgrade"), String::from("websocket"))
+            .header(String::from("Connection"), String::from("Upgrade"))
+            .header(String::from("Sec-WebSocket-Accept"), accept_key(key));
+        http_channel.response.headers.remove("Content-Length");
+        http_channel.response.write_head(http_channel.stream)?;
+        http_channel.stream.flush()?;
+        http_channel.is_sent = true;
+        // the connection ends with the WebSocket, also when the handler fails
+        http_channel.response.header(String::from("Connection"), String::from("close"));
+        // messages may be far apart, the idle timeout of HTTP connections doesn't apply
+        let stream = http_channel.stream.try_clone()?;
+        stream.set_read_timeout(None)?;
+        (self.handler)(http_channel.request, WebSocket::new(stream, Role::Server))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use crate::response::HttpResponse;
+    use crate::utils::get_stream;
+    use super::*;
+
+    /// Reads from the input and collects what is written.
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // the frames a client sends for the messages, fragments of at most `frame_size` bytes
+    fn client_frames(messages: &[Message], frame_size: usize) -> Vec<u8> {
+        let mut client = WebSocket::new(Pipe { input: Cursor::new(Vec::new()), output: Vec::new() }, Role::Client);
+        client.max_frame_size(frame_size);
+        for message in messages {
+            client.send(message).unwrap();
+        }
+        client.stream.output
+    }
+
+    fn server(input: Vec<u8>) -> WebSocket<Pipe> {
+        WebSocket::new(Pipe { input: Cursor::new(input), output: Vec::new() }, Role::Server)
+    }
+
+    #[test]
+    fn test_accept_key() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_check_handshake() {
+        let request = |headers: &str| HttpRequest::new(format!("GET /ws HTTP/1.1\nHost: a\n{}\n", headers)).unwrap();
+        let valid = request("Upgrade: websocket\nConnection: keep-alive, Upgrade\nSec-WebSocket-Version: 13\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\n");
+        assert_eq!(check_handshake(&valid).unwrap(), "dGhlIHNhbXBsZSBub25jZQ==");
+        let no_upgrade = request("Sec-WebSocket-Version: 13\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\n");
+        assert_eq!(check_handshake(&no_upgrade).unwrap_err().status, StatusCode::UPGRADE_REQUIRED);
+        let bad_key = request("Upgrade: websocket\nConnection: Upgrade\nSec-WebSocket-Version: 13\nSec-WebSocket-Key: abc\n");
+        assert_eq!(check_handshake(&bad_key).unwrap_err().status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_head_not_upgraded() {
+        let mapping = WebSocketMapping::new(Regex::new(r"^/ws$").unwrap(), |_, _| Ok(()));
+        let http_request = HttpRequest::new("HEAD /ws HTTP/1.1\nHost: a\nUpgrade: websocket\nConnection: Upgrade\n\
+            Sec-WebSocket-Version: 13\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\n\n").unwrap();
+        assert!(mapping.predicate(&http_request));
+        let mut http_response = HttpResponse::for_request(&http_request, None);
+        let mut stream = get_stream(8093);
+        let mut channel = HttpChannel::new(&http_request, &mut http_response, &mut stream);
+        mapping.handle(&mut channel).unwrap();
+        assert!(!channel.is_sent);
+        assert_eq!(channel.response.status, StatusCode::UPGRADE_REQUIRED);
+        assert!(channel.response.keep_alive());
+    }
+
+    #[test]
+    fn test_read_messages() {
+        let messages = [
+            Message::Text(String::from("hello")),
+            Message::Ping(b"p".to_vec()),
+            Message::Binary(vec![7; 70000]),
+            Message::Text("fragmented ünïcode".repeat(3)),
+            Message::Close(Some((CLOSE_NORMAL, String::from("bye")))),
+        ];
+        let mut server = server(client_frames(&messages[..3], 1 << 20));
+        server.stream.input.get_mut().extend(client_frames(&messages[3..], 7));
+        for expected in &messages {
+            assert_eq!(&server.read_message().unwrap(), expected);
+        }
+        assert!(matches!(server.read_message(), Err(WebSocketError::Closed)));
+        // the ping is answered with a pong and the close is echoed
+        assert_eq!(server.stream.output, [&[0x8A, 1, b'p'][..], &[0x88, 2, 0x03, 0xE8]].concat());
+    }
+
+    #[test]
+    fn test_ping_between_fragments() {
+        let mut input = client_frames(&[Message::Text(String::from("abcdef"))], 3);
+        let second_fragment = input.split_off(9);
+        input.extend(client_frames(&[Message::Ping(Vec::new())], 3));
+        input.extend(second_fragment);
+        let mut server = server(input);
+        assert_eq!(server.read_message().unwrap(), Message::Ping(Vec::new()));
+        assert_eq!(server.read_message().unwrap(), Message::Text(String::from("abcdef")));
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        // unmasked frame from a client
+        let mut unmasked = server(vec![0x81, 1, b'a']);
+        assert!(matches!(unmasked.read_message(), Err(WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, _))));
+        // answered with a close frame carrying 1002
+        assert_eq!(&unmasked.stream.output[..1], &[0x88]);
+        assert_eq!(&unmasked.stream.output[2..4], &[0x03, 0xEA]);
+        assert!(matches!(unmasked.read_message(), Err(WebSocketError::Closed)));
+
+        let mut invalid_text = server(client_frames(&[Message::Binary(vec![0xff])], 10));
+        invalid_text.stream.input.get_mut()[0] = 0x81;
+        assert!(matches!(invalid_text.read_message(), Err(WebSocketError::Protocol(CLOSE_INVALID_DATA, _))));
+
+        let mut too_big = server(client_frames(&[Message::Binary(vec![0; 100])], 30));
+        too_big.max_message_size(50);
+        assert!(matches!(too_big.read_message(), Err(WebSocketError::Protocol(CLOSE_TOO_BIG, _))));
+
+        // 1005 only means that a close frame had no code, it is answered with 1002 and not echoed
+        let mut reserved_code = server(client_frames(&[Message::Close(Some((1005, String::new())))], 10));
+        assert!(matches!(reserved_code.read_message(), Err(WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, _))));
+        assert_eq!(&reserved_code.stream.output[2..4], &[0x03, 0xEA]);
+        for code in [999, 1004, 1006, 1015, 2999, 5000] {
+            assert!(!is_valid_close_code(code));
+        }
+        for code in [1000, 1003, 1007, 1011, 3000, 4999] {
+            assert!(is_valid_close_code(code));
+        }
+    }
+
+    #[test]
+    fn test_send() {
+        let mut server = server(Vec::new());
+        server.max_frame_size(3);
+        server.send_text("abcd").unwrap();
+        assert_eq!(server.stream.output, [0x01, 3, b'a', b'b', b'c', 0x80, 1, b'd']);
+        server.close(CLOSE_NORMAL, "").unwrap();
+        assert!(matches!(server.send_text("late"), Err(WebSocketError::Closed)));
+    }
+}
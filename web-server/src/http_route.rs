@@ -1,12 +1,29 @@
 use std::error::Error;
+use std::net::TcpStream;
 use std::thread;
 use std::time::Duration;
 use regex::Regex;
+use serde::Serialize;
+use http::body::Body;
 use http::channel::HttpChannel;
-use http::router::{HttpRouter, RegexMapping};
+use http::compression::Compression;
+use http::multipart::MultipartLimits;
+use http::request::HttpRequest;
+use http::router::{HttpRouter, RegexMapping, Route};
+use http::sse::is_disconnect;
 use http::static_mapping::StaticMapping;
+use http::templates::{set_templates, Templates};
+use http::websocket::{Message, WebSocket, WebSocketError, WebSocketMapping};
+
+// largest upload accepted by /upload
+const MAX_UPLOAD_SIZE: usize = 1024 * 1024 * 1024;
 
 pub fn route(router: &mut HttpRouter) -> Result<(), Box<dyn Error>> {
+    router.compression(Compression::default());
+    let mut templates = Templates::new("templates");
+    // pick up template edits without a restart in debug builds
+    templates.reload(cfg!(debug_assertions));
+    set_templates(templates);
     router.route(Box::new(StaticMapping::new()));
     router.route(Box::new(
         RegexMapping::GET(Regex::new(r"^/abc$")?, my_handler)));
@@ -14,6 +31,18 @@ pub fn route(router: &mut HttpRouter) -> Result<(), Box<dyn Error>> {
         RegexMapping::POST(Regex::new(r"^/post$")?, post_handler)));
     router.route(Box::new(
         RegexMapping::GET(Regex::new(r"^/post$")?, post_handler)));
+    router.route(Box::new(
+        Route::new(RegexMapping::POST(Regex::new(r"^/upload$")?, upload_handler))
+            .stream_body(true)
+            .max_body_size(MAX_UPLOAD_SIZE)));
+    router.route(Box::new(
+        RegexMapping::GET(Regex::new(r"^/export\.csv$")?, export_handler)));
+    router.route(Box::new(
+        RegexMapping::GET(Regex::new(r"^/events$")?, events_handler)));
+    router.route(Box::new(
+        WebSocketMapping::new(Regex::new(r"^/echo$")?, echo_handler)));
+    router.route(Box::new(
+        RegexMapping::GET(Regex::new(r"^/admin$")?, admin_handler)));
     Ok(())
 }
 
@@ -24,15 +53,99 @@ fn my_handler(channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
 }
 
 fn post_handler(channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
+    println!("client: {:?}", channel.request.connection().peer_addr);
     println!("method: {:?}", channel.request.method);
     println!("path: {:?}", channel.request.path);
     println!("headers: {:?}", channel.request.headers);
-    println!("body: {:?}", channel.request.body());
-    channel.response
-        .header("Content-Type".to_string(), "application/json".to_string())
-        .body_str(String::from("{
-  \"name\": \"my-name\",
-  \"params\": [1, 2, 3]
-}"));
+    println!("body: {:?}", channel.request.body_str());
+    println!("form: {:?}", channel.request.form());
+    if let Ok(mut multipart) = channel.request.multipart(MultipartLimits::default()) {
+        while let Some(part) = multipart.next_part()? {
+            println!("part: {} {:?} {} bytes", part.name, part.filename, part.size());
+        }
+    }
+    channel.response.json(&Post { name: String::from("my-name"), params: vec![1, 2, 3] })?;
+    Ok(())
+}
+
+/// Receives large files as they arrive, parts over the memory threshold go to temp files.
+fn upload_handler(channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
+    let limits = MultipartLimits { max_part_size: MAX_UPLOAD_SIZE, max_total_size: MAX_UPLOAD_SIZE, ..Default::default() };
+    let mut multipart = channel.multipart(limits)?;
+    let mut received = String::new();
+    while let Some(part) = multipart.next_part()? {
+        received.push_str(&format!("{} {:?} {} bytes\n", part.name, part.filename, part.size()));
+    }
+    drop(multipart);
+    channel.response.body_str(received);
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Streams a generated CSV without holding it in memory.
+fn export_handler(channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
+    let mut rows = 0..100_000;
+    channel.response.header(String::from("Content-Type"), String::from("text/csv"));
+    channel.send_body(Body::from_fn(move || rows.next().map(|it| Ok(format!("{},row {}\n", it, it).into_bytes()))))?;
+    Ok(())
+}
+
+/// Pushes build steps as Server-Sent Events, a reconnecting client resumes after its last step.
+fn events_handler(channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
+    let mut events = channel.event_stream()?;
+    let first = events.last_event_id().and_then(|it| it.parse::<u32>().ok()).map_or(1, |it| it + 1);
+    for step in first..=5 {
+        match events.send(Some("build"), &format!("step {} of 5", step), Some(&step.to_string())) {
+            Err(e) if is_disconnect(&e) => {
+                println!("client left at step {}", step);
+                return Ok(());
+            }
+            result => result?,
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    events.close()?;
+    Ok(())
+}
+
+/// Renders the admin page, values like the user agent are escaped by the template.
+fn admin_handler(channel: &mut HttpChannel) -> Result<(), Box<dyn Error>> {
+    let request = channel.request;
+    let connection = Connection {
+        id: request.connection().id,
+        peer: request.connection().peer_addr.map(|it| it.to_string()).unwrap_or_default(),
+        user_agent: request.header("User-Agent").unwrap_or("").to_string(),
+    };
+    channel.response.render("admin.html", Admin { server: String::from("rust-web-server"), connections: vec![connection] })?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Admin {
+    server: String,
+    connections: Vec<Connection>,
+}
+
+#[derive(Serialize)]
+struct Connection {
+    id: u64,
+    peer: String,
+    user_agent: String,
+}
+
+#[derive(Serialize)]
+struct Post {
+    name: String,
+    params: Vec<i32>,
+}
+
+/// Sends text and binary messages back until the client closes.
+fn echo_handler(_request: &HttpRequest, mut socket: WebSocket<TcpStream>) -> Result<(), Box<dyn Error>> {
+    loop {
+        match socket.read_message() {
+            Ok(message @ (Message::Text(_) | Message::Binary(_))) => socket.send(&message)?,
+            Ok(Message::Close(_)) | Err(WebSocketError::Closed) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+}
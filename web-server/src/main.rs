@@ -1,15 +1,27 @@
 mod http_route;
 
-use std::io::{Write};
-use std::net::{TcpListener, TcpStream};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
 use std::error::Error;
 use std::sync::{Arc};
 use std::thread;
+use std::time::Duration;
 use http::channel::HttpChannel;
-use http::request::HttpRequest;
-use http::response::HttpResponse;
+use http::connection::ConnectionInfo;
+use http::limits::RequestLimits;
+use http::reader::{BodyReader, HttpReader};
+use http::request::{HttpRequest, ParseError};
+use http::response::{write_interim, HttpResponse};
 use http::router::{HttpRouter};
-use http::utils::MyRead;
+use http::status::StatusCode;
+
+// idle time after which a kept-alive connection is closed
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+// how long a client may pause while sending a request body, e.g. a large upload
+const BODY_READ_TIMEOUT: Duration = Duration::from_secs(60);
+// how long and how much unread input is drained before closing a rejected connection
+const LINGER_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_LINGER_BYTES: usize = 1024 * 1024;
 
 fn main() {
     match TcpListener::bind("0.0.0.0:8085") {
@@ -17,12 +29,14 @@ fn main() {
             let mut router = HttpRouter::new();
             http_route::route(&mut router).expect("route mapping error");
             let router = Arc::new(router);
+            let limits = RequestLimits::default();
             for stream in listener.incoming() {
                 match stream {
                     Ok(stream) => {
                         let router = Arc::clone(&router);
+                        let limits = limits.clone();
                         thread::spawn(move || {
-                            if let Err(e) = handle_connection(stream, &router) {
+                            if let Err(e) = handle_connection(stream, &router, limits) {
                                 eprintln!("handle error: {}", e);
                             }
                         });
@@ -40,26 +54,133 @@ fn main() {
     }
 }
 
-fn handle_connection(mut stream: TcpStream, router: &HttpRouter) -> Result<(), Box<dyn Error>> {
-    let input = stream.read_all_string()?;
-    let input = dbg!(input);
-    if input.is_empty() {
-        return Ok(());
+fn handle_connection(mut stream: TcpStream, router: &HttpRouter, limits: RequestLimits) -> Result<(), Box<dyn Error>> {
+    let max_body_size = limits.max_body_size;
+    let mut reader = HttpReader::with_limits(stream.try_clone()?, limits);
+    reader.connection(ConnectionInfo::from_stream(&stream));
+    loop {
+        // the reader shares the socket, its timeout applies to both
+        stream.set_read_timeout(Some(KEEP_ALIVE_TIMEOUT))?;
+        let mut http_request = match reader.read_head() {
+            Ok(Some(http_request)) => http_request,
+            Ok(None) => return Ok(()),
+            Err(e) => return reject(stream, e, router),
+        };
+        stream.set_read_timeout(Some(BODY_READ_TIMEOUT))?;
+        let max_body_size = router.max_body_size(&http_request).unwrap_or(max_body_size);
+        // the route decides on the head alone, so a refused upload is never sent
+        let accepted = http_request.expects_continue()
+            .and_then(|expects_continue| router.guard(&http_request).map(|_| expects_continue));
+        let expects_continue = match accepted {
+            Ok(expects_continue) => expects_continue,
+            Err(e) => {
+                let mut http_response = router.response(&http_request);
+                http_response.handler_error(&e);
+                return refuse(stream, &mut http_response);
+            }
+        };
+        if let Err(e) = reader.check_body(&http_request, max_body_size) {
+            return reject(stream, e, router);
+        }
+        if expects_continue {
+            write_interim(&mut stream, StatusCode::CONTINUE)?;
+        }
+        if router.stream_body(&http_request) {
+            let mut body = match reader.body_reader(&http_request, max_body_size) {
+                Ok(body) => body,
+                Err(e) => return reject(stream, e, router),
+            };
+            let keep_alive = handle_request(&http_request, Some(&mut body), &mut stream, router)?;
+            if !body.is_finished() {
+                close_gracefully(stream);
+                return Ok(());
+            }
+            if !keep_alive {
+                return Ok(());
+            }
+            continue;
+        }
+        if let Err(e) = reader.read_body(&mut http_request, max_body_size) {
+            return reject(stream, e, router);
+        }
+        if !handle_request(&http_request, None, &mut stream, router)? {
+            return Ok(());
+        }
     }
-    let http_request = HttpRequest::new(&input);
-    let mut http_response = HttpResponse::new();
-    let mut http_channel = HttpChannel::new(&http_request, &mut http_response, &mut stream);
-    if let Err(e) = router.handle(&mut http_channel) {
-        eprintln!("handle error: {}", e);
-        http_channel.response.error().body_str(e.to_string());
-    };
-    if !http_channel.is_sent {
-        let output = http_channel.response.to_string();
-        http_channel.stream.write(dbg!(output).as_bytes())?;
-        http_channel.stream.flush()?;
+}
+
+/// Answers a request that could not be read and closes the connection.
+fn reject(stream: TcpStream, e: ParseError, router: &HttpRouter) -> Result<(), Box<dyn Error>> {
+    match e {
+        ParseError::Io(e) if is_timeout(&e) => Ok(()),
+        ParseError::Io(e) => Err(e.into()),
+        e => {
+            eprintln!("parse error: {}", e);
+            let mut http_response = HttpResponse::new();
+            if let Some(server_name) = router.server_name_ref() {
+                http_response.header(String::from("Server"), server_name.to_string());
+            }
+            http_response.parse_error(&e);
+            refuse(stream, &mut http_response)
+        }
     }
+}
+
+/// Sends the response and closes the connection, the unread body of the request is discarded.
+fn refuse(mut stream: TcpStream, http_response: &mut HttpResponse) -> Result<(), Box<dyn Error>> {
+    http_response.header(String::from("Connection"), String::from("close"));
+    http_response.write_to(&mut stream)?;
+    stream.flush()?;
+    close_gracefully(stream);
     Ok(())
 }
 
+/// Stops writing, then drains what the client is still sending for a moment, so the
+/// unread input doesn't reset the connection before the client has read our response.
+fn close_gracefully(mut stream: TcpStream) {
+    if stream.shutdown(Shutdown::Write).is_err() || stream.set_read_timeout(Some(LINGER_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut buffer = [0; 8192];
+    let mut drained = 0;
+    while drained < MAX_LINGER_BYTES {
+        match stream.read(&mut buffer) {
+            Ok(n) if n > 0 => drained += n,
+            _ => return,
+        }
+    }
+}
 
+/// Handles one request on the connection, returns whether the connection stays open.
+/// A streamed body the handler didn't read to the end ends the connection.
+fn handle_request(http_request: &HttpRequest, mut body: Option<&mut BodyReader<TcpStream>>, stream: &mut TcpStream,
+                  router: &HttpRouter) -> Result<bool, Box<dyn Error>> {
+    let mut http_response = router.response(http_request);
+    let is_sent = {
+        let mut http_channel = HttpChannel::new(http_request, &mut http_response, stream);
+        if let Some(body) = body.as_deref_mut() {
+            http_channel.streamed_body(body);
+        }
+        if let Err(e) = router.handle(&mut http_channel) {
+            eprintln!("handle error: {}", e);
+            // once the head is out the error can't be answered, the connection is closed instead
+            if !http_channel.is_sent {
+                http_channel.response.handler_error(e.as_ref());
+            }
+        };
+        http_channel.is_sent
+    };
+    let body_finished = body.map_or(true, |it| it.is_finished());
+    if !body_finished {
+        http_response.header(String::from("Connection"), String::from("close"));
+    }
+    if !is_sent {
+        http_response.write_to(stream)?;
+        stream.flush()?;
+    }
+    Ok(http_response.keep_alive() && body_finished)
+}
 
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut
+}